@@ -1,4 +1,22 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use block_multiplier::batch::mul_batch;
+use block_multiplier::config::Bn254Fr;
+use block_multiplier::ifma::{block_multiplier_ifma, LANES};
+use block_multiplier::inv::{batch_inv, inv, inv_ct};
+use block_multiplier::goldilocks;
+use block_multiplier::lanes;
+#[cfg(feature = "rayon")]
+use block_multiplier::pool::{par_mul_batch, RoundToZeroPool};
+use block_multiplier::pow::{pow, pow_fixed, pow_lockstep, sqrt};
+use block_multiplier::pseudo_mersenne::{self, Curve25519, PseudoMersenne, Secp256k1};
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use block_multiplier::u52;
+use criterion::measurement::WallTime;
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput,
+};
 use rand::prelude::StdRng;
 use rand::{Rng, SeedableRng};
 
@@ -49,7 +67,7 @@ fn bench_block_multiplier(c: &mut Criterion) {
 
     group.bench_function("block_multiplier", |bencher| {
         bencher.iter(|| {
-            block_multiplier::block_multiplier(
+            block_multiplier::block_multiplier::<Bn254Fr>(
                 black_box(s0_a),
                 black_box(s0_b),
                 black_box(v0_a),
@@ -60,6 +78,267 @@ fn bench_block_multiplier(c: &mut Criterion) {
         })
     });
 
+    let ifma_a: [[u64; 4]; LANES] = std::array::from_fn(|_| rng.random());
+    let ifma_b: [[u64; 4]; LANES] = std::array::from_fn(|_| rng.random());
+
+    group.bench_function("block_multiplier_ifma", |bencher| {
+        bencher.iter(|| block_multiplier_ifma::<Bn254Fr>(black_box(ifma_a), black_box(ifma_b)))
+    });
+
+    #[cfg(any(
+        all(target_arch = "aarch64", target_feature = "neon"),
+        target_arch = "x86_64"
+    ))]
+    {
+        let u52_a = u52::from_u256::<Bn254Fr>([v0_a, v1_a]);
+        let u52_b = u52::from_u256::<Bn254Fr>([v0_b, v1_b]);
+        group.bench_function("u52_mul", |bencher| {
+            bencher.iter(|| u52::mul::<Bn254Fr>(black_box(u52_a), black_box(u52_b)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_block_squarer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block_squarer");
+
+    let seed: u64 = rand::random();
+    println!("Using random seed for benchmark: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let s0_a: [u64; 4] = rng.random();
+    let v0_a: [u64; 4] = rng.random();
+    let v1_a: [u64; 4] = rng.random();
+
+    group.bench_function("block_squarer", |bencher| {
+        bencher.iter(|| {
+            block_multiplier::block_squarer::<Bn254Fr>(
+                black_box(s0_a),
+                black_box(v0_a),
+                black_box(v1_a),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_lanes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block_multiplier_lanes");
+
+    let seed: u64 = rand::random();
+    println!("Using random seed for benchmark: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    bench_lane_mix::<1, 2>(&mut group, &mut rng);
+    bench_lane_mix::<2, 2>(&mut group, &mut rng);
+    bench_lane_mix::<1, 4>(&mut group, &mut rng);
+    bench_lane_mix::<0, 4>(&mut group, &mut rng);
+    bench_lane_mix::<2, 4>(&mut group, &mut rng);
+
+    group.finish();
+}
+
+/// S scalar and V vector multiplications per call, throughput in multiplications
+fn bench_lane_mix<const S: usize, const V: usize>(
+    group: &mut BenchmarkGroup<WallTime>,
+    rng: &mut StdRng,
+) {
+    let s_a: [[u64; 4]; S] = rng.random();
+    let s_b: [[u64; 4]; S] = rng.random();
+    let v_a: [[u64; 4]; V] = rng.random();
+    let v_b: [[u64; 4]; V] = rng.random();
+
+    group.throughput(Throughput::Elements((S + V) as u64));
+    group.bench_function(format!("{S}+{V}"), |bencher| {
+        bencher.iter(|| {
+            lanes::block_multiplier::<Bn254Fr, S, V>(
+                black_box(s_a),
+                black_box(s_b),
+                black_box(v_a),
+                black_box(v_b),
+            )
+        })
+    });
+}
+
+fn bench_special_form(c: &mut Criterion) {
+    let mut group = c.benchmark_group("special_form");
+
+    let seed: u64 = rand::random();
+    println!("Using random seed for benchmark: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    group.throughput(Throughput::Elements(3));
+    group.bench_function("block_multiplier_bn254", |bencher| {
+        let ab: [[u64; 4]; 6] = rng.random();
+        bencher.iter(|| {
+            let ab = black_box(ab);
+            block_multiplier::block_multiplier::<Bn254Fr>(ab[0], ab[1], ab[2], ab[3], ab[4], ab[5])
+        })
+    });
+    bench_pseudo_mersenne::<Curve25519>(&mut group, &mut rng, "curve25519");
+    bench_pseudo_mersenne::<Secp256k1>(&mut group, &mut rng, "secp256k1");
+
+    let s_a: [u64; 2] = rng.random();
+    let s_b: [u64; 2] = rng.random();
+    let v_a: [u64; 4] = rng.random();
+    let v_b: [u64; 4] = rng.random();
+    group.throughput(Throughput::Elements(6));
+    group.bench_function("goldilocks_2+4", |bencher| {
+        bencher.iter(|| {
+            goldilocks::block_multiplier::<2, 4>(
+                black_box(s_a),
+                black_box(s_b),
+                black_box(v_a),
+                black_box(v_b),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_pseudo_mersenne<F: PseudoMersenne>(
+    group: &mut BenchmarkGroup<WallTime>,
+    rng: &mut StdRng,
+    name: &str,
+) {
+    let ab: [[u64; 4]; 6] = rng.random();
+    group.bench_function(format!("block_multiplier_{name}"), |bencher| {
+        bencher.iter(|| {
+            let ab = black_box(ab);
+            pseudo_mersenne::block_multiplier::<F>(ab[0], ab[1], ab[2], ab[3], ab[4], ab[5])
+        })
+    });
+}
+
+fn bench_mul_batch(c: &mut Criterion) {
+    const BATCH_SIZE: usize = 1 << 12;
+    let mut group = c.benchmark_group("mul_batch");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+
+    let seed: u64 = rand::random();
+    println!("Using random seed for benchmark: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let a: Vec<[u64; 4]> = (0..BATCH_SIZE).map(|_| rng.random()).collect();
+    let b: Vec<[u64; 4]> = (0..BATCH_SIZE).map(|_| rng.random()).collect();
+    let mut out = vec![[0u64; 4]; BATCH_SIZE];
+
+    group.bench_function("mul_batch", |bencher| {
+        bencher.iter(|| mul_batch::<Bn254Fr>(black_box(&a), black_box(&b), black_box(&mut out)))
+    });
+
+    group.bench_function("block_multiplier_loop", |bencher| {
+        bencher.iter(|| {
+            let (a, b) = (black_box(&a), black_box(&b));
+            for i in (0..BATCH_SIZE - 2).step_by(3) {
+                (out[i], out[i + 1], out[i + 2]) = block_multiplier::block_multiplier::<Bn254Fr>(
+                    a[i],
+                    b[i],
+                    a[i + 1],
+                    b[i + 1],
+                    a[i + 2],
+                    b[i + 2],
+                );
+            }
+            // BATCH_SIZE leaves a single element
+            (out[BATCH_SIZE - 1], _, _) = block_multiplier::block_multiplier::<Bn254Fr>(
+                a[BATCH_SIZE - 1],
+                b[BATCH_SIZE - 1],
+                a[BATCH_SIZE - 1],
+                b[BATCH_SIZE - 1],
+                a[BATCH_SIZE - 1],
+                b[BATCH_SIZE - 1],
+            );
+            black_box(&mut out);
+        })
+    });
+
+    #[cfg(feature = "rayon")]
+    {
+        let pool = RoundToZeroPool::new().unwrap();
+        group.bench_function("par_mul_batch", |bencher| {
+            bencher.iter(|| {
+                par_mul_batch::<Bn254Fr>(&pool, black_box(&a), black_box(&b), black_box(&mut out))
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_inv(c: &mut Criterion) {
+    const BATCH_SIZE: usize = 1 << 12;
+    let mut group = c.benchmark_group("inv");
+
+    let seed: u64 = rand::random();
+    println!("Using random seed for benchmark: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Below 2^253 such that the inputs satisfy the input bound
+    let mut random = || {
+        let mut a: [u64; 4] = rng.random();
+        a[3] >>= 3;
+        a
+    };
+
+    let a = random();
+
+    group.bench_function("inv", |bencher| {
+        bencher.iter(|| inv::<Bn254Fr>(black_box(a)))
+    });
+
+    group.bench_function("inv_ct", |bencher| {
+        bencher.iter(|| inv_ct::<Bn254Fr>(black_box(a)))
+    });
+
+    let values: Vec<[u64; 4]> = (0..BATCH_SIZE).map(|_| random()).collect();
+    let mut out = vec![[0u64; 4]; BATCH_SIZE];
+
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    group.bench_function("batch_inv", |bencher| {
+        bencher.iter(|| batch_inv::<Bn254Fr>(black_box(&values), black_box(&mut out)))
+    });
+
+    group.finish();
+}
+
+fn bench_pow(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pow");
+
+    let seed: u64 = rand::random();
+    println!("Using random seed for benchmark: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // Below 2^253 such that the inputs satisfy the input bound
+    let mut random = || {
+        let mut a: [u64; 4] = rng.random();
+        a[3] >>= 3;
+        a
+    };
+
+    let bases = [random(), random(), random()];
+    let exps = [random(), random(), random()];
+
+    group.bench_function("pow", |bencher| {
+        bencher.iter(|| pow::<Bn254Fr>(black_box(bases[0]), black_box(exps[0])))
+    });
+
+    group.bench_function("pow_fixed", |bencher| {
+        bencher.iter(|| pow_fixed::<Bn254Fr>(black_box(bases[0]), black_box(exps[0])))
+    });
+
+    group.bench_function("pow_lockstep", |bencher| {
+        bencher.iter(|| pow_lockstep::<Bn254Fr>(black_box(bases), black_box(exps)))
+    });
+
+    group.bench_function("sqrt", |bencher| {
+        bencher.iter(|| sqrt::<Bn254Fr>(black_box(bases[0])))
+    });
+
     group.finish();
 }
 
@@ -70,6 +349,7 @@ criterion_group!(
         // Warm up is warm because it literally warms up the pi
         .warm_up_time(std::time::Duration::new(1,0))
         .measurement_time(std::time::Duration::new(10,0));
-    targets = bench_block_multiplier
+    targets = bench_block_multiplier, bench_block_squarer, bench_lanes, bench_special_form,
+        bench_mul_batch, bench_inv, bench_pow
 );
 criterion_main!(benches);
@@ -0,0 +1,373 @@
+//! Modular addition, subtraction, negation, doubling and reduction in the representation of
+//! [`crate::block_multiplier`].
+//!
+//! Bounds, for values in Montgomery form:
+//!
+//! - inputs: below [`FieldConfig::OUTPUT_MAX`] = 2^256 - 2P, which includes every output of
+//!   [`crate::block_multiplier`] for inputs below P;
+//! - outputs: below P.
+//!
+//! The outputs are therefore valid inputs for [`crate::block_multiplier`], whose outputs are again
+//! valid inputs here. Outputs of the multiplier can't be fed back into the multiplier directly: for
+//! inputs near `OUTPUT_MAX` its results exceed `OUTPUT_MAX` (while staying congruent).
+//!
+//! The results are reduced with a fixed sequence of conditional subtractions of 2^k P, the
+//! smallest such multiple that covers `OUTPUT_MAX` first. For the BN254 fields these are 4P, 2P and
+//! P. There are no data dependent branches.
+//!
+//! The `_simd` variants take the limbs transposed as by [`crate::transpose_u256_to_simd`] and
+//! compute the same results lane by lane.
+
+use crate::config::FieldConfig;
+use crate::derivation::{add as add_carry, sub as sub_borrow};
+use std::simd::cmp::SimdPartialOrd;
+use std::simd::{Select, Simd};
+
+/// (a + b) mod P, below P for a, b below [`FieldConfig::OUTPUT_MAX`]
+#[inline(always)]
+pub fn add<F: FieldConfig>(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    debug_assert_input::<F>(a);
+    debug_assert_input::<F>(b);
+    let (sum, carry) = add_carry(a, b);
+    reduce_ladder::<F>(sum, carry as u64)
+}
+
+/// (a - b) mod P, below P for a, b below [`FieldConfig::OUTPUT_MAX`]
+#[inline(always)]
+pub fn sub<F: FieldConfig>(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    debug_assert_input::<F>(a);
+    debug_assert_input::<F>(b);
+    // a + (2^k P - b) can't underflow as b < OUTPUT_MAX <= 2^k P
+    let (m, m_hi) = const { shl(F::P, ladder_top(F::P, F::OUTPUT_MAX)) };
+    let (neg_b, borrow) = sub_borrow(m, b);
+    let (sum, carry) = add_carry(a, neg_b);
+    reduce_ladder::<F>(sum, m_hi - borrow as u64 + carry as u64)
+}
+
+/// -a mod P, below P for a below [`FieldConfig::OUTPUT_MAX`]
+#[inline(always)]
+pub fn neg<F: FieldConfig>(a: [u64; 4]) -> [u64; 4] {
+    debug_assert_input::<F>(a);
+    let (m, m_hi) = const { shl(F::P, ladder_top(F::P, F::OUTPUT_MAX)) };
+    let (neg_a, borrow) = sub_borrow(m, a);
+    reduce_ladder::<F>(neg_a, m_hi - borrow as u64)
+}
+
+/// 2a mod P, below P for a below [`FieldConfig::OUTPUT_MAX`]
+#[inline(always)]
+pub fn double<F: FieldConfig>(a: [u64; 4]) -> [u64; 4] {
+    add::<F>(a, a)
+}
+
+/// a mod P, for a below [`FieldConfig::OUTPUT_MAX`]
+#[inline(always)]
+pub fn reduce<F: FieldConfig>(a: [u64; 4]) -> [u64; 4] {
+    debug_assert_input::<F>(a);
+    reduce_ladder::<F>(a, 0)
+}
+
+/// [`ladder`] from the smallest 2^k P that covers `OUTPUT_MAX`
+#[inline(always)]
+fn reduce_ladder<F: FieldConfig>(v: [u64; 4], hi: u64) -> [u64; 4] {
+    let top = const { ladder_top(F::P, F::OUTPUT_MAX) };
+    ladder::<F>(v, hi, top)
+}
+
+/// a mod P for any a below 2^256, which takes one more step than [`reduce`]
+#[inline(always)]
+pub fn reduce_u256<F: FieldConfig>(a: [u64; 4]) -> [u64; 4] {
+    let top = const { ladder_top(F::P, [u64::MAX; 4]) };
+    ladder::<F>(a, 0, top)
+}
+
+/// Subtract 2^k P for k = top, ..., 0 whenever it doesn't underflow, which takes the 320 bit value
+/// (v, hi) below 2^(top+1) P to below P.
+#[inline(always)]
+fn ladder<F: FieldConfig>(mut v: [u64; 4], mut hi: u64, top: u32) -> [u64; 4] {
+    for k in (0..=top).rev() {
+        let (m, m_hi) = shl(F::P, k);
+        let (diff, borrow) = sub_borrow(v, m);
+        let (diff_hi, underflow1) = hi.overflowing_sub(m_hi);
+        let (diff_hi, underflow2) = diff_hi.overflowing_sub(borrow as u64);
+        // All ones when the difference is kept
+        let keep = ((underflow1 | underflow2) as u64).wrapping_sub(1);
+        for i in 0..v.len() {
+            v[i] = (diff[i] & keep) | (v[i] & !keep);
+        }
+        hi = (diff_hi & keep) | (hi & !keep);
+    }
+    debug_assert_eq!(hi, 0);
+    v
+}
+
+/// Lane wise [`add`]
+#[inline(always)]
+pub fn add_simd<F: FieldConfig, const L: usize>(
+    a: [Simd<u64, L>; 4],
+    b: [Simd<u64, L>; 4],
+) -> [Simd<u64, L>; 4] {
+    let (sum, carry) = add_carry_simd(a, b);
+    reduce_ladder_simd::<F, L>(sum, carry)
+}
+
+/// Lane wise [`sub`]
+#[inline(always)]
+pub fn sub_simd<F: FieldConfig, const L: usize>(
+    a: [Simd<u64, L>; 4],
+    b: [Simd<u64, L>; 4],
+) -> [Simd<u64, L>; 4] {
+    let (m, m_hi) = const { shl(F::P, ladder_top(F::P, F::OUTPUT_MAX)) };
+    let (neg_b, borrow) = sub_borrow_simd(m.map(Simd::splat), b);
+    let (sum, carry) = add_carry_simd(a, neg_b);
+    reduce_ladder_simd::<F, L>(sum, Simd::splat(m_hi) - borrow + carry)
+}
+
+/// Lane wise [`neg`]
+#[inline(always)]
+pub fn neg_simd<F: FieldConfig, const L: usize>(a: [Simd<u64, L>; 4]) -> [Simd<u64, L>; 4] {
+    let (m, m_hi) = const { shl(F::P, ladder_top(F::P, F::OUTPUT_MAX)) };
+    let (neg_a, borrow) = sub_borrow_simd(m.map(Simd::splat), a);
+    reduce_ladder_simd::<F, L>(neg_a, Simd::splat(m_hi) - borrow)
+}
+
+/// Lane wise [`double`]
+#[inline(always)]
+pub fn double_simd<F: FieldConfig, const L: usize>(a: [Simd<u64, L>; 4]) -> [Simd<u64, L>; 4] {
+    add_simd::<F, L>(a, a)
+}
+
+/// Lane wise [`reduce_ladder`]
+#[inline(always)]
+fn reduce_ladder_simd<F: FieldConfig, const L: usize>(
+    mut v: [Simd<u64, L>; 4],
+    mut hi: Simd<u64, L>,
+) -> [Simd<u64, L>; 4] {
+    let top = const { ladder_top(F::P, F::OUTPUT_MAX) };
+    for k in (0..=top).rev() {
+        let (m, m_hi) = shl(F::P, k);
+        let (diff, borrow) = sub_borrow_simd(v, m.map(Simd::splat));
+        let m_hi = Simd::splat(m_hi) + borrow;
+        // m_hi is at most 2, so the sum doesn't wrap
+        let keep = hi.simd_ge(m_hi);
+        for i in 0..v.len() {
+            v[i] = keep.select(diff[i], v[i]);
+        }
+        hi = keep.select(hi - m_hi, hi);
+    }
+    v
+}
+
+/// a + b and the carry out of each lane
+#[inline(always)]
+fn add_carry_simd<const L: usize>(
+    a: [Simd<u64, L>; 4],
+    b: [Simd<u64, L>; 4],
+) -> ([Simd<u64, L>; 4], Simd<u64, L>) {
+    let mut out = [Simd::splat(0); 4];
+    let mut carry = Simd::splat(0);
+    for i in 0..out.len() {
+        let sum1 = a[i] + b[i];
+        let sum2 = sum1 + carry;
+        let overflow = sum1.simd_lt(a[i]) | sum2.simd_lt(carry);
+        out[i] = sum2;
+        carry = overflow.select(Simd::splat(1), Simd::splat(0));
+    }
+    (out, carry)
+}
+
+/// a - b and the borrow out of each lane
+#[inline(always)]
+fn sub_borrow_simd<const L: usize>(
+    a: [Simd<u64, L>; 4],
+    b: [Simd<u64, L>; 4],
+) -> ([Simd<u64, L>; 4], Simd<u64, L>) {
+    let mut out = [Simd::splat(0); 4];
+    let mut borrow = Simd::splat(0);
+    for i in 0..out.len() {
+        let diff1 = a[i] - b[i];
+        let diff2 = diff1 - borrow;
+        let underflow = a[i].simd_lt(b[i]) | diff1.simd_lt(borrow);
+        out[i] = diff2;
+        borrow = underflow.select(Simd::splat(1), Simd::splat(0));
+    }
+    (out, borrow)
+}
+
+/// The smallest k with 2^k P >= bound
+const fn ladder_top(p: [u64; 4], bound: [u64; 4]) -> u32 {
+    let mut k = 0;
+    loop {
+        let (m, m_hi) = shl(p, k);
+        if m_hi > 0 || !sub_borrow(m, bound).1 {
+            return k;
+        }
+        k += 1;
+    }
+}
+
+/// a << k and the bits shifted out, for k < 64
+#[inline(always)]
+const fn shl(a: [u64; 4], k: u32) -> ([u64; 4], u64) {
+    // Shifting by 64 - k in two steps also covers k = 0
+    (
+        [
+            a[0] << k,
+            (a[1] << k) | (a[0] >> 1) >> (63 - k),
+            (a[2] << k) | (a[1] >> 1) >> (63 - k),
+            (a[3] << k) | (a[2] >> 1) >> (63 - k),
+        ],
+        (a[3] >> 1) >> (63 - k),
+    )
+}
+
+#[inline(always)]
+fn debug_assert_input<F: FieldConfig>(a: [u64; 4]) {
+    debug_assert!(
+        sub_borrow(a, F::OUTPUT_MAX).1,
+        "input is not below OUTPUT_MAX"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bn254Fq, Bn254Fr};
+    use crate::test_utils::{mod_mul, random_below};
+    use crate::{block_multiplier, transpose_simd_to_u256, transpose_u256_to_simd};
+    use primitive_types::U256;
+    use rand::{Rng, SeedableRng, rngs};
+
+    fn check_ops<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = U256(F::P);
+        let max = U256(F::OUTPUT_MAX);
+        let edges = [
+            U256::zero(),
+            U256::one(),
+            p - 1,
+            p,
+            p * 2,
+            p * 3,
+            max - p,
+            max - 1,
+        ];
+
+        let mut inputs: Vec<U256> = edges.to_vec();
+        inputs.extend((0..200).map(|_| U256(random_below(&mut rng, F::OUTPUT_MAX))));
+
+        for &a in &inputs {
+            let expected_neg = (p - a % p) % p;
+            let expected_double = (a % p + a % p) % p;
+            assert_eq!(U256(neg::<F>(a.0)), expected_neg);
+            assert_eq!(U256(double::<F>(a.0)), expected_double);
+            assert_eq!(U256(reduce::<F>(a.0)), a % p);
+            for &b in &inputs {
+                let expected_add = (a % p + b % p) % p;
+                let expected_sub = (a % p + p - b % p) % p;
+                assert_eq!(U256(add::<F>(a.0, b.0)), expected_add);
+                assert_eq!(U256(sub::<F>(a.0, b.0)), expected_sub);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ops() {
+        check_ops::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_ops_bn254_fq() {
+        check_ops::<Bn254Fq>();
+    }
+
+    fn check_simd_matches_scalar<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for _ in 0..10000 {
+            let a = [
+                random_below(&mut rng, F::OUTPUT_MAX),
+                random_below(&mut rng, F::OUTPUT_MAX),
+            ];
+            let b = [
+                random_below(&mut rng, F::OUTPUT_MAX),
+                random_below(&mut rng, F::OUTPUT_MAX),
+            ];
+            let va = transpose_u256_to_simd(a);
+            let vb = transpose_u256_to_simd(b);
+
+            let sum = transpose_simd_to_u256(add_simd::<F, 2>(va, vb));
+            let diff = transpose_simd_to_u256(sub_simd::<F, 2>(va, vb));
+            let negated = transpose_simd_to_u256(neg_simd::<F, 2>(va));
+            let doubled = transpose_simd_to_u256(double_simd::<F, 2>(va));
+            for lane in 0..2 {
+                assert_eq!(sum[lane], add::<F>(a[lane], b[lane]));
+                assert_eq!(diff[lane], sub::<F>(a[lane], b[lane]));
+                assert_eq!(negated[lane], neg::<F>(a[lane]));
+                assert_eq!(doubled[lane], double::<F>(a[lane]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        check_simd_matches_scalar::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_simd_matches_scalar_bn254_fq() {
+        check_simd_matches_scalar::<Bn254Fq>();
+    }
+
+    #[test]
+    fn test_reduce_u256() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = U256(Bn254Fr::P);
+        for a in (0..10000)
+            .map(|_| rng.random())
+            .chain([[u64::MAX; 4], Bn254Fr::OUTPUT_MAX])
+        {
+            assert_eq!(U256(reduce_u256::<Bn254Fr>(a)), U256(a) % p);
+        }
+    }
+
+    #[test]
+    fn test_ladder_top() {
+        // 4P is the smallest multiple of the form 2^k P that covers 2^256 - 2P
+        assert_eq!(ladder_top(Bn254Fr::P, Bn254Fr::OUTPUT_MAX), 2);
+        assert_eq!(ladder_top(Bn254Fq::P, Bn254Fq::OUTPUT_MAX), 2);
+        // and 8P that of 2^256 - 1
+        assert_eq!(ladder_top(Bn254Fr::P, [u64::MAX; 4]), 3);
+    }
+
+    // Alternate multiplications and additions without reducing in between
+    #[test]
+    fn test_chain_with_block_multiplier() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = U256(Bn254Fr::P);
+        let max = U256(Bn254Fr::OUTPUT_MAX);
+        let r_inv = U256(Bn254Fr::R_INV);
+        let mod_mul = |a, b| mod_mul(a, b, p);
+
+        let mut x: [u64; 4] = (U256(rng.random::<[u64; 4]>()) % p).0;
+        let mut y: [u64; 4] = (U256(rng.random::<[u64; 4]>()) % p).0;
+        let (mut x_ref, mut y_ref) = (U256(x), U256(y));
+        for _ in 0..10000 {
+            // x, y < P on entry, the products are below OUTPUT_MAX
+            let (xy, xx, yy) = block_multiplier::<Bn254Fr>(x, y, x, x, y, y);
+            for out in [xy, xx, yy] {
+                assert!(U256(out) < max);
+            }
+            // x <- xy + xx, y <- yy - 2xy
+            x = add::<Bn254Fr>(xy, xx);
+            y = sub::<Bn254Fr>(yy, double::<Bn254Fr>(xy));
+
+            let xy_ref = mod_mul(mod_mul(x_ref, y_ref), r_inv);
+            let xx_ref = mod_mul(mod_mul(x_ref, x_ref), r_inv);
+            let yy_ref = mod_mul(mod_mul(y_ref, y_ref), r_inv);
+            x_ref = (xy_ref + xx_ref) % p;
+            y_ref = (yy_ref + p + p - xy_ref - xy_ref) % p;
+
+            assert_eq!(U256(x), x_ref);
+            assert_eq!(U256(y), y_ref);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Montgomery multiplication of slices of field elements with [`crate::block_multiplier`].
+
+use crate::config::FieldConfig;
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use crate::{block_multiplier_rtz, rounding::RoundToZeroGuard, scalar_mul};
+
+/// Element wise Montgomery multiplication `out[i] = a[i] * b[i]`.
+///
+/// The elements are processed in triples of one scalar and two vector lanes, and the rounding mode
+/// is switched only once for the whole batch. A tail of one element is multiplied on the scalar
+/// lane only. The results are the same as those of calling [`crate::block_multiplier`] on
+/// consecutive triples.
+///
+/// Panics if the slices don't have the same length.
+pub fn mul_batch<F: FieldConfig>(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    assert_eq!(a.len(), b.len(), "input lengths differ");
+    assert_eq!(a.len(), out.len(), "output length differs");
+
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { mul_batch_avx2::<F>(a, b, out) };
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    mul_batch_impl::<F>(a, b, out);
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    mul_batch_scalar::<F>(a, b, out);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn mul_batch_avx2<F: FieldConfig>(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    mul_batch_impl::<F>(a, b, out)
+}
+
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn mul_batch_impl<F: FieldConfig>(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    let guard = RoundToZeroGuard::new();
+    mul_batch_rtz_impl::<F>(a, b, out);
+    drop(guard);
+}
+
+/// The batch loop of [`mul_batch`] for threads that are in round toward zero mode already
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+pub(crate) fn mul_batch_rtz_impl<F: FieldConfig>(
+    a: &[[u64; 4]],
+    b: &[[u64; 4]],
+    out: &mut [[u64; 4]],
+) {
+    let mut a_chunks = a.chunks_exact(3);
+    let mut b_chunks = b.chunks_exact(3);
+    let mut out_chunks = out.chunks_exact_mut(3);
+    for ((a, b), out) in (&mut a_chunks).zip(&mut b_chunks).zip(&mut out_chunks) {
+        (out[0], out[1], out[2]) = block_multiplier_rtz::<F>(a[0], b[0], a[1], b[1], a[2], b[2]);
+    }
+
+    match (
+        a_chunks.remainder(),
+        b_chunks.remainder(),
+        out_chunks.into_remainder(),
+    ) {
+        ([a0], [b0], [out0]) => *out0 = scalar_mul::<F>(*a0, *b0),
+        ([a0, a1], [b0, b1], [out0, out1]) => {
+            // The second vector lane is unused and repeats the first
+            (*out0, *out1, _) = block_multiplier_rtz::<F>(*a0, *b0, *a1, *b1, *a1, *b1);
+        }
+        _ => {}
+    }
+}
+
+#[cfg_attr(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    allow(dead_code)
+)]
+pub(crate) fn mul_batch_scalar<F: FieldConfig>(
+    a: &[[u64; 4]],
+    b: &[[u64; 4]],
+    out: &mut [[u64; 4]],
+) {
+    for ((a, b), out) in a.chunks(3).zip(b.chunks(3)).zip(out.chunks_mut(3)) {
+        // Only the first element of a triple goes to the scalar lane, the others are emulated
+        // vector lanes
+        let a1 = *a.get(1).unwrap_or(&a[0]);
+        let b1 = *b.get(1).unwrap_or(&b[0]);
+        let a2 = *a.get(2).unwrap_or(&a1);
+        let b2 = *b.get(2).unwrap_or(&b1);
+        let (s0, v0, v1) = crate::scalar::block_multiplier::<F>(a[0], b[0], a1, b1, a2, b2);
+        let results = [s0, v0, v1];
+        out.copy_from_slice(&results[..out.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_multiplier;
+    use crate::config::Bn254Fr;
+    use rand::{Rng, SeedableRng, rngs};
+
+    type MulBatch = fn(&[[u64; 4]], &[[u64; 4]], &mut [[u64; 4]]);
+
+    fn check_mul_batch(mul_batch: MulBatch) {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for len in 0..20 {
+            let a: Vec<[u64; 4]> = (0..len).map(|_| rng.random()).collect();
+            let b: Vec<[u64; 4]> = (0..len).map(|_| rng.random()).collect();
+            let mut out = vec![[0; 4]; len];
+            mul_batch(&a, &b, &mut out);
+
+            for i in (0..len).step_by(3) {
+                let j = (i + 1).min(len - 1);
+                let k = (i + 2).min(len - 1);
+                let (s0, v0, v1) = block_multiplier::<Bn254Fr>(a[i], b[i], a[j], b[j], a[k], b[k]);
+                assert_eq!(out[i], s0);
+                assert_eq!(out[j], if j == i { s0 } else { v0 });
+                assert_eq!(out[k], if k == j { out[j] } else { v1 });
+            }
+        }
+    }
+
+    #[test]
+    fn test_mul_batch() {
+        check_mul_batch(mul_batch::<Bn254Fr>);
+    }
+
+    #[test]
+    fn test_mul_batch_scalar() {
+        check_mul_batch(mul_batch_scalar::<Bn254Fr>);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_mul_batch_length_mismatch() {
+        mul_batch::<Bn254Fr>(&[[0; 4]; 3], &[[0; 4]; 3], &mut [[0; 4]; 2]);
+    }
+}
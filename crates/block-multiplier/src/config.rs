@@ -0,0 +1,169 @@
+use crate::constants;
+use crate::derivation::{
+    inv_pow2_mod, neg_inv_mod_2_64, output_max, parse_u256, pow2_mod, root_of_unity, two_adicity,
+    u256_to_u260,
+};
+
+/// Field parameters used by [`crate::block_multiplier`].
+///
+/// The scalar lane works in 4x64 bit limbs and the vector lanes in 5x52 bit limbs, so every
+/// constant is needed in the limb size of the lane that consumes it. Only the modulus has to be
+/// provided, all other constants are derived from it at compile time.
+///
+/// The vector lanes shift the inputs left by 2 bits (see `u256_to_u260_shl2_simd`) such that the
+/// 2^260 Montgomery radix of the 52 bit limbs results in the same 2^256 Montgomery form as the
+/// scalar lane. This and the single reduction step require the modulus to leave 2 bits of headroom:
+/// P < 2^254. BN254's scalar and base field satisfy this, BLS12-381's scalar field and secp256k1 do
+/// not and are rejected when the multiplier is instantiated:
+///
+/// ```compile_fail
+/// use block_multiplier::config::FieldConfig;
+/// use block_multiplier::derivation::parse_u256;
+///
+/// struct Secp256k1;
+///
+/// impl FieldConfig for Secp256k1 {
+///     const P: [u64; 4] =
+///         parse_u256("0xfffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f");
+/// }
+///
+/// block_multiplier::block_multiplier::<Secp256k1>([0; 4], [0; 4], [0; 4], [0; 4], [0; 4], [0; 4]);
+/// ```
+pub trait FieldConfig {
+    /// Modulus
+    const P: [u64; 4];
+    /// -P^-1 mod 2^64
+    const NP0: u64 = neg_inv_mod_2_64(Self::P[0]);
+    /// R mod P with R = 2^256
+    const R: [u64; 4] = pow2_mod(256, Self::P);
+    /// R^2 mod P
+    const R2: [u64; 4] = pow2_mod(512, Self::P);
+    /// R^3 mod P
+    const R3: [u64; 4] = pow2_mod(768, Self::P);
+    /// R^-1 mod P
+    const R_INV: [u64; 4] = inv_pow2_mod(256, Self::P);
+    /// 2^256 - 2P, the outputs of [`crate::block_multiplier`] are below this bound when its inputs
+    /// are below P
+    const OUTPUT_MAX: [u64; 4] = output_max(Self::P);
+    /// Largest S such that 2^S divides P - 1
+    const TWO_ADICITY: u32 = two_adicity(Self::P);
+    /// Primitive 2^TWO_ADICITY-th root of unity in Montgomery form
+    const ROOT_OF_UNITY: [u64; 4] = root_of_unity(Self::P);
+
+    /// -P^-1 mod 2^52
+    const U52_NP0: u64 = Self::NP0 & constants::MASK52;
+    /// Modulus in 52 bit limbs
+    const U52_P: [u64; 5] = u256_to_u260(Self::P);
+    /// 2^520 mod P in 52 bit limbs, R^2 for the 2^260 radix of the vector lanes
+    const U52_R2: [u64; 5] = u256_to_u260(pow2_mod(520, Self::P));
+
+    /// 2^-64 mod P
+    const U64_I1: [u64; 4] = inv_pow2_mod(64, Self::P);
+    /// 2^-128 mod P
+    const U64_I2: [u64; 4] = inv_pow2_mod(128, Self::P);
+    /// 2^-192 mod P
+    const U64_I3: [u64; 4] = inv_pow2_mod(192, Self::P);
+
+    /// 2^-52 mod P in 52 bit limbs
+    const RHO_1: [u64; 5] = u256_to_u260(inv_pow2_mod(52, Self::P));
+    /// 2^-104 mod P in 52 bit limbs
+    const RHO_2: [u64; 5] = u256_to_u260(inv_pow2_mod(104, Self::P));
+    /// 2^-156 mod P in 52 bit limbs
+    const RHO_3: [u64; 5] = u256_to_u260(inv_pow2_mod(156, Self::P));
+    /// 2^-208 mod P in 52 bit limbs
+    const RHO_4: [u64; 5] = u256_to_u260(inv_pow2_mod(208, Self::P));
+}
+
+/// Scalar field of BN254
+pub struct Bn254Fr;
+
+impl FieldConfig for Bn254Fr {
+    const P: [u64; 4] = constants::P;
+}
+
+/// Base field of BN254
+pub struct Bn254Fq;
+
+impl FieldConfig for Bn254Fq {
+    const P: [u64; 4] =
+        parse_u256("21888242871839275222246405745257275088696311157297823662689037894645226208583");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Known values for a modulus that isn't derived from the BN254 scalar field constants
+    #[test]
+    fn bn254_fq_constants() {
+        assert_eq!(
+            Bn254Fq::P,
+            [
+                0x3c208c16d87cfd47,
+                0x97816a916871ca8d,
+                0xb85045b68181585d,
+                0x30644e72e131a029,
+            ]
+        );
+        assert_eq!(Bn254Fq::NP0, 0x87d20782e4866389);
+        assert_eq!(Bn254Fq::U52_NP0, 0x20782e4866389);
+        assert_eq!(
+            Bn254Fq::R2,
+            [
+                0xf32cfc5b538afa89,
+                0xb5e71911d44501fb,
+                0x47ab1eff0a417ff6,
+                0x06d89f71cab8351f,
+            ]
+        );
+        assert_eq!(
+            Bn254Fq::U52_R2,
+            [
+                0x8a81d1966eb04,
+                0x6195018016b86,
+                0xb4f898c98e615,
+                0x9969bfd531600,
+                0x00a8469a30d3a,
+            ]
+        );
+        assert_eq!(
+            Bn254Fq::U64_I3,
+            [
+                0xb334aa7264874f53,
+                0x62a52db096edbc9e,
+                0x235878f5c0a1dafe,
+                0x28f5dd496ed1da9d,
+            ]
+        );
+        assert_eq!(
+            Bn254Fq::OUTPUT_MAX,
+            [
+                0x87bee7d24f060572,
+                0xd0fd2add2f1c6ae5,
+                0x8f5f7492fcfd4f44,
+                0x9f37631a3d9cbfac,
+            ]
+        );
+        assert_eq!(Bn254Fq::TWO_ADICITY, 1);
+        // -1 in Montgomery form
+        assert_eq!(
+            Bn254Fq::ROOT_OF_UNITY,
+            [
+                0x68c3488912edefaa,
+                0x8d087f6872aabf4f,
+                0x51e1a24709081231,
+                0x2259d6b14729c0fa,
+            ]
+        );
+        assert_eq!(
+            Bn254Fq::RHO_4,
+            [
+                0x8a16bb6633b48,
+                0x043d957942a28,
+                0xb109e04f8f77c,
+                0xb2269cd913056,
+                0x01a8b3dc97a74,
+            ]
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! `const fn` derivation of the field constants from the modulus alone.
+//!
+//! The derivations do all arithmetic modulo P through repeated doubling and halving. That is slow
+//! and branches on the values, but it only runs at compile time and is easy to check by hand.
+//!
+//! The carry propagating helpers `add`, `sub` and `shr` are also used at runtime by the other
+//! modules, they have no data dependent branches. So is `half_mod`, which does branch and is only
+//! used by the variable time [`crate::inv::inv`].
+
+use crate::constants::MASK52;
+
+/// Parse a 256 bit number from a decimal string or from a hexadecimal string prefixed with `0x`.
+///
+/// Panics (at compile time when used in a constant) on invalid digits or overflow.
+pub const fn parse_u256(s: &str) -> [u64; 4] {
+    let bytes = s.as_bytes();
+    let (radix, mut i) = if bytes.len() > 2 && bytes[0] == b'0' && (bytes[1] | 0x20) == b'x' {
+        (16, 2)
+    } else {
+        (10, 0)
+    };
+    assert!(i < bytes.len(), "empty number");
+
+    let mut out = [0u64; 4];
+    while i < bytes.len() {
+        let c = bytes[i];
+        i += 1;
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' if radix == 16 => c - b'a' + 10,
+            b'A'..=b'F' if radix == 16 => c - b'A' + 10,
+            b'_' => continue,
+            _ => panic!("invalid digit"),
+        };
+
+        // out = out * radix + digit
+        let mut carry = digit as u128;
+        let mut j = 0;
+        while j < out.len() {
+            let tmp = out[j] as u128 * radix + carry;
+            out[j] = tmp as u64;
+            carry = tmp >> 64;
+            j += 1;
+        }
+        assert!(carry == 0, "number does not fit in 256 bits");
+    }
+    out
+}
+
+/// Check the requirements that [`crate::block_multiplier`] puts on the modulus.
+///
+/// The vector lanes shift the inputs left by 2 bits and the scalar lane does a single reduction
+/// step, both only fit when P < 2^254. Montgomery multiplication requires P to be odd.
+pub const fn assert_modulus(p: [u64; 4]) {
+    assert!(p[0] & 1 == 1, "modulus must be odd");
+    assert!(p[3] >> 62 == 0, "modulus must be smaller than 2^254");
+}
+
+/// -P^-1 mod 2^64
+pub const fn neg_inv_mod_2_64(p0: u64) -> u64 {
+    assert!(p0 & 1 == 1, "modulus must be odd");
+    // Newton iteration doubles the number of correct bits. p0 is its own inverse mod 2^3.
+    let mut inv = p0;
+    let mut i = 0;
+    while i < 5 {
+        inv = inv.wrapping_mul(2u64.wrapping_sub(p0.wrapping_mul(inv)));
+        i += 1;
+    }
+    inv.wrapping_neg()
+}
+
+/// 2^k mod P
+pub const fn pow2_mod(k: usize, p: [u64; 4]) -> [u64; 4] {
+    let mut out = reduce([1, 0, 0, 0], p);
+    let mut i = 0;
+    while i < k {
+        out = double_mod(out, p);
+        i += 1;
+    }
+    out
+}
+
+/// 2^-k mod P
+pub const fn inv_pow2_mod(k: usize, p: [u64; 4]) -> [u64; 4] {
+    let mut out = reduce([1, 0, 0, 0], p);
+    let mut i = 0;
+    while i < k {
+        out = half_mod(out, p);
+        i += 1;
+    }
+    out
+}
+
+/// Convert 4x64 bit limbs into 5x52 bit limbs
+pub const fn u256_to_u260(limbs: [u64; 4]) -> [u64; 5] {
+    let [l0, l1, l2, l3] = limbs;
+    [
+        l0 & MASK52,
+        ((l0 >> 52) | (l1 << 12)) & MASK52,
+        ((l1 >> 40) | (l2 << 24)) & MASK52,
+        ((l2 >> 28) | (l3 << 36)) & MASK52,
+        l3 >> 16,
+    ]
+}
+
+pub const fn u260_to_f52(limbs: [u64; 5]) -> [f64; 5] {
+    let mut out = [0.; 5];
+    let mut i = 0;
+    while i < limbs.len() {
+        out[i] = limbs[i] as f64;
+        i += 1;
+    }
+    out
+}
+
+/// 2^256 - 2P
+pub const fn output_max(p: [u64; 4]) -> [u64; 4] {
+    let (two_p, _) = add(p, p);
+    sub([0; 4], two_p).0
+}
+
+/// Largest S such that 2^S divides P - 1
+pub const fn two_adicity(p: [u64; 4]) -> u32 {
+    let p_minus_one = sub(p, [1, 0, 0, 0]).0;
+    let mut i = 0;
+    while p_minus_one[i] == 0 {
+        i += 1;
+    }
+    i as u32 * 64 + p_minus_one[i].trailing_zeros()
+}
+
+/// Primitive 2^S-th root of unity in Montgomery form with S the [`two_adicity`]: the smallest
+/// quadratic non-residue raised to the power (P - 1) / 2^S.
+pub const fn root_of_unity(p: [u64; 4]) -> [u64; 4] {
+    let p_minus_one = sub(p, [1, 0, 0, 0]).0;
+    let minus_one = sub(p, pow2_mod(256, p)).0;
+    let r2 = pow2_mod(512, p);
+
+    // Euler's criterion: z^((P - 1) / 2) = -1 exactly for non-residues
+    let mut z = 2;
+    let z_mont = loop {
+        let z_mont = mont_mul([z, 0, 0, 0], r2, p);
+        if eq(mont_pow(z_mont, shr(p_minus_one, 1), p), minus_one) {
+            break z_mont;
+        }
+        z += 1;
+    };
+    mont_pow(z_mont, shr(p_minus_one, two_adicity(p)), p)
+}
+
+/// a * b * 2^-256 mod P for a, b < P < 2^255, by coarsely integrated operand scanning
+pub const fn mont_mul(a: [u64; 4], b: [u64; 4], p: [u64; 4]) -> [u64; 4] {
+    let np0 = neg_inv_mod_2_64(p[0]);
+    let mut t = [0u64; 5];
+    let mut i = 0;
+    while i < a.len() {
+        // t += a[i] * b
+        let mut carry = 0;
+        let mut j = 0;
+        while j < b.len() {
+            let tmp = t[j] as u128 + a[i] as u128 * b[j] as u128 + carry;
+            t[j] = tmp as u64;
+            carry = tmp >> 64;
+            j += 1;
+        }
+        let tmp = t[4] as u128 + carry;
+        t[4] = tmp as u64;
+        let t5 = (tmp >> 64) as u64;
+
+        // t = (t + m * P) / 2^64, which is exact for this choice of m
+        let m = t[0].wrapping_mul(np0);
+        let mut carry = (t[0] as u128 + m as u128 * p[0] as u128) >> 64;
+        let mut j = 1;
+        while j < p.len() {
+            let tmp = t[j] as u128 + m as u128 * p[j] as u128 + carry;
+            t[j - 1] = tmp as u64;
+            carry = tmp >> 64;
+            j += 1;
+        }
+        let tmp = t[4] as u128 + carry;
+        t[3] = tmp as u64;
+        t[4] = t5 + (tmp >> 64) as u64;
+        i += 1;
+    }
+    // t < 2P < 2^256
+    reduce([t[0], t[1], t[2], t[3]], p)
+}
+
+/// a^e in Montgomery form for a < P in Montgomery form
+pub const fn mont_pow(a: [u64; 4], e: [u64; 4], p: [u64; 4]) -> [u64; 4] {
+    let mut out = pow2_mod(256, p);
+    let mut i = 256;
+    while i > 0 {
+        i -= 1;
+        out = mont_mul(out, out, p);
+        if (e[i / 64] >> (i % 64)) & 1 == 1 {
+            out = mont_mul(out, a, p);
+        }
+    }
+    out
+}
+
+// -- [HELPERS] ------------------------------------------------------------------------------------
+
+/// a + b and the carry out
+pub(crate) const fn add(a: [u64; 4], b: [u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0; 4];
+    let mut carry = false;
+    let mut i = 0;
+    while i < out.len() {
+        let (sum1, overflow1) = a[i].overflowing_add(b[i]);
+        let (sum2, overflow2) = sum1.overflowing_add(carry as u64);
+        out[i] = sum2;
+        carry = overflow1 | overflow2;
+        i += 1;
+    }
+    (out, carry)
+}
+
+/// a - b and the borrow out
+pub(crate) const fn sub(a: [u64; 4], b: [u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0; 4];
+    let mut borrow = false;
+    let mut i = 0;
+    while i < out.len() {
+        let (diff1, underflow1) = a[i].overflowing_sub(b[i]);
+        let (diff2, underflow2) = diff1.overflowing_sub(borrow as u64);
+        out[i] = diff2;
+        borrow = underflow1 | underflow2;
+        i += 1;
+    }
+    (out, borrow)
+}
+
+/// a >> k for k < 256
+pub(crate) const fn shr(a: [u64; 4], k: u32) -> [u64; 4] {
+    let limbs = (k / 64) as usize;
+    let bits = k % 64;
+    let mut out = [0; 4];
+    let mut i = 0;
+    while i + limbs < a.len() {
+        out[i] = a[i + limbs] >> bits;
+        if bits > 0 && i + limbs + 1 < a.len() {
+            out[i] |= a[i + limbs + 1] << (64 - bits);
+        }
+        i += 1;
+    }
+    out
+}
+
+pub(crate) const fn eq(a: [u64; 4], b: [u64; 4]) -> bool {
+    a[0] == b[0] && a[1] == b[1] && a[2] == b[2] && a[3] == b[3]
+}
+
+/// a mod P by repeated subtraction
+pub(crate) const fn reduce(mut a: [u64; 4], p: [u64; 4]) -> [u64; 4] {
+    loop {
+        let (diff, borrow) = sub(a, p);
+        if borrow {
+            return a;
+        }
+        a = diff;
+    }
+}
+
+/// 2a mod P for a < P
+const fn double_mod(a: [u64; 4], p: [u64; 4]) -> [u64; 4] {
+    let (sum, carry) = add(a, a);
+    let (diff, borrow) = sub(sum, p);
+    // The doubled value only exceeds 256 bits when it is also larger than P
+    if carry || !borrow { diff } else { sum }
+}
+
+/// a/2 mod P for a < P
+pub(crate) const fn half_mod(a: [u64; 4], p: [u64; 4]) -> [u64; 4] {
+    let (a, carry) = if a[0] & 1 == 1 { add(a, p) } else { (a, false) };
+    [
+        (a[0] >> 1) | (a[1] << 63),
+        (a[1] >> 1) | (a[2] << 63),
+        (a[2] >> 1) | (a[3] << 63),
+        (a[3] >> 1) | ((carry as u64) << 63),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::P;
+
+    #[test]
+    fn parse_hex_and_decimal() {
+        let dec = parse_u256(
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617",
+        );
+        let hex = parse_u256("0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001");
+        assert_eq!(dec, hex);
+        assert_eq!(
+            hex,
+            [
+                0x43e1f593f0000001,
+                0x2833e84879b97091,
+                0xb85045b68181585d,
+                0x30644e72e131a029,
+            ]
+        );
+    }
+
+    // The values below were computed independently of this module and used to be the hardcoded
+    // BN254 constants
+    #[test]
+    fn bn254_montgomery_constants() {
+        assert_eq!(neg_inv_mod_2_64(P[0]), 0xc2e1f593efffffff);
+        assert_eq!(neg_inv_mod_2_64(P[0]) & MASK52, 0x1F593EFFFFFFF);
+        assert_eq!(
+            pow2_mod(256, P),
+            [
+                0xac96341c4ffffffb,
+                0x36fc76959f60cd29,
+                0x666ea36f7879462e,
+                0x0e0a77c19a07df2f,
+            ]
+        );
+        assert_eq!(
+            pow2_mod(512, P),
+            [
+                0x1BB8E645AE216DA7,
+                0x53FE3AB1E35C59E3,
+                0x8C49833D53BB8085,
+                0x0216D0B17F4E44A5,
+            ]
+        );
+        assert_eq!(
+            inv_pow2_mod(256, P),
+            [
+                0xdc5ba0056db1194e,
+                0x090ef5a9e111ec87,
+                0xc8260de4aeb85d5d,
+                0x15ebf95182c5551c,
+            ]
+        );
+        assert_eq!(
+            u256_to_u260(pow2_mod(520, P)),
+            [
+                0x0B852D16DA6F5,
+                0xC621620CDDCE3,
+                0xAF1B95343FFB6,
+                0xC3C15E103E7C2,
+                0x00281528FA122,
+            ]
+        );
+        assert_eq!(
+            u256_to_u260(P),
+            [
+                0x1F593F0000001,
+                0x4879B9709143E,
+                0x181585D2833E8,
+                0xA029B85045B68,
+                0x030644E72E131,
+            ]
+        );
+    }
+
+    #[test]
+    fn bn254_root_of_unity() {
+        assert_eq!(two_adicity(P), 28);
+        // 5^((P - 1) / 2^28), also used by arkworks
+        let root = parse_u256("0x2a3c09f0a58a7e8500e0a7eb8ef62abc402d111e41112ed49bd61b6e725b19f0");
+        assert_eq!(root_of_unity(P), mont_mul(root, pow2_mod(512, P), P));
+        assert_eq!(
+            root_of_unity(P),
+            [
+                0x636e735580d13d9c,
+                0xa22bf3742445ffd6,
+                0x56452ac01eb203d8,
+                0x1860ef942963f9e7,
+            ]
+        );
+    }
+
+    #[test]
+    fn bn254_reduction_tables() {
+        assert_eq!(
+            inv_pow2_mod(64, P),
+            [
+                0x2d3e8053e396ee4d,
+                0xca478dbeab3c92cd,
+                0xb2d8f06f77f52a93,
+                0x24d6ba07f7aa8f04,
+            ]
+        );
+        assert_eq!(
+            inv_pow2_mod(128, P),
+            [
+                0x18ee753c76f9dc6f,
+                0x54ad7e14a329e70f,
+                0x2b16366f4f7684df,
+                0x133100d71fdf3579,
+            ]
+        );
+        assert_eq!(
+            inv_pow2_mod(192, P),
+            [
+                0x9BACB016127CBE4E,
+                0x0B2051FA31944124,
+                0xB064EEA46091C76C,
+                0x2B062AAA49F80C7D,
+            ]
+        );
+        assert_eq!(
+            u256_to_u260(inv_pow2_mod(52, P)),
+            [
+                0x82e644ee4c3d2,
+                0xf93893c98b1de,
+                0xd46fe04d0a4c7,
+                0x8f0aad55e2a1f,
+                0x005ed0447de83,
+            ]
+        );
+        assert_eq!(
+            u256_to_u260(inv_pow2_mod(104, P)),
+            [
+                0x74eccce9a797a,
+                0x16ddcc30bd8a4,
+                0x49ecd3539499e,
+                0xb23a6fcc592b8,
+                0x00e3bd49f6ee5,
+            ]
+        );
+        assert_eq!(
+            u256_to_u260(inv_pow2_mod(156, P)),
+            [
+                0x0E8C656567D77,
+                0x430D05713AE61,
+                0xEA3BA6B167128,
+                0xA7DAE55C5A296,
+                0x01B4AFD513572,
+            ]
+        );
+        assert_eq!(
+            u256_to_u260(inv_pow2_mod(208, P)),
+            [
+                0x22E2400E2F27D,
+                0x323B46EA19686,
+                0xE6C43F0DF672D,
+                0x7824014C39E8B,
+                0x00C6B48AFE1B8,
+            ]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Field element type on top of the Montgomery multipliers.
+
+use crate::arith::{add, reduce_u256, sub};
+use crate::config::{Bn254Fr, FieldConfig};
+use crate::scalar_mul;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Element of the field `F` in Montgomery form.
+///
+/// The limbs can be any representative below 2^256, such as the partially reduced outputs of
+/// [`crate::block_multiplier`]. Operations reduce their operands as required by the multipliers
+/// and equality compares the canonical values. Reductions are a fixed sequence of conditional
+/// subtractions, see [`crate::arith`].
+pub struct MontFp<F: FieldConfig = Bn254Fr> {
+    limbs: [u64; 4],
+    _field: PhantomData<F>,
+}
+
+impl<F: FieldConfig> MontFp<F> {
+    pub const ZERO: Self = Self::from_montgomery([0; 4]);
+    pub const ONE: Self = Self::from_montgomery(F::R);
+
+    /// Convert a canonical value, which is reduced modulo P first, into Montgomery form by
+    /// multiplying with R^2.
+    pub fn from_canonical(value: [u64; 4]) -> Self {
+        Self::from_montgomery(scalar_mul::<F>(reduce_u256::<F>(value), F::R2))
+    }
+
+    /// Convert back to the canonical value below P.
+    ///
+    /// Montgomery multiplication by one multiplies by R^-1 mod P.
+    pub fn to_canonical(&self) -> [u64; 4] {
+        reduce_u256::<F>(scalar_mul::<F>(self.reduce().limbs, [1, 0, 0, 0]))
+    }
+
+    /// Wrap limbs that are already in Montgomery form, such as the outputs of
+    /// [`crate::block_multiplier`].
+    pub const fn from_montgomery(limbs: [u64; 4]) -> Self {
+        Self {
+            limbs,
+            _field: PhantomData,
+        }
+    }
+
+    /// The limbs in Montgomery form as they are stored, not necessarily reduced.
+    pub const fn to_montgomery(&self) -> [u64; 4] {
+        self.limbs
+    }
+
+    /// The same element with its Montgomery form reduced below P.
+    pub fn reduce(&self) -> Self {
+        Self::from_montgomery(reduce_u256::<F>(self.limbs))
+    }
+}
+
+impl<F: FieldConfig> Clone for MontFp<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F: FieldConfig> Copy for MontFp<F> {}
+
+impl<F: FieldConfig> PartialEq for MontFp<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.reduce().limbs == other.reduce().limbs
+    }
+}
+
+impl<F: FieldConfig> Eq for MontFp<F> {}
+
+impl<F: FieldConfig> fmt::Debug for MontFp<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MontFp").field(&self.to_canonical()).finish()
+    }
+}
+
+impl<F: FieldConfig> Add for MontFp<F> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::from_montgomery(add::<F>(self.reduce().limbs, rhs.reduce().limbs))
+    }
+}
+
+impl<F: FieldConfig> Sub for MontFp<F> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::from_montgomery(sub::<F>(self.reduce().limbs, rhs.reduce().limbs))
+    }
+}
+
+impl<F: FieldConfig> Neg for MontFp<F> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::ZERO - self
+    }
+}
+
+impl<F: FieldConfig> Mul for MontFp<F> {
+    type Output = Self;
+
+    /// Multiplies on the scalar lane of [`crate::block_multiplier`], the result is below
+    /// 2^256 - 2P but not reduced.
+    fn mul(self, rhs: Self) -> Self {
+        // The output bound only holds for inputs below P
+        Self::from_montgomery(scalar_mul::<F>(self.reduce().limbs, rhs.reduce().limbs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Bn254Fq;
+    use crate::test_utils::{mod_mul, random_below};
+    use primitive_types::U256;
+    use rand::{SeedableRng, rngs};
+
+    fn check_ops<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = U256(F::P);
+        let mod_mul = |a, b| mod_mul(a, b, p);
+
+        for _ in 0..10000 {
+            let a = U256(random_below(&mut rng, F::P));
+            let b = U256(random_below(&mut rng, F::P));
+            let a_mont = MontFp::<F>::from_canonical(a.0);
+            let b_mont = MontFp::<F>::from_canonical(b.0);
+
+            assert_eq!(U256(a_mont.to_canonical()), a);
+            assert_eq!(U256((a_mont + b_mont).to_canonical()), (a + b) % p);
+            assert_eq!(U256((a_mont - b_mont).to_canonical()), (a + p - b) % p);
+            assert_eq!(U256((-a_mont).to_canonical()), (p - a) % p);
+            assert_eq!(U256((a_mont * b_mont).to_canonical()), mod_mul(a, b));
+            assert_eq!(a_mont * b_mont, b_mont * a_mont);
+            assert_eq!(a_mont * (b_mont + MontFp::ONE), a_mont * b_mont + a_mont);
+        }
+    }
+
+    #[test]
+    fn test_mont_fp() {
+        check_ops::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_mont_fp_bn254_fq() {
+        check_ops::<Bn254Fq>();
+    }
+
+    #[test]
+    fn test_partially_reduced_eq() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let a = U256(random_below(&mut rng, Bn254Fr::P));
+        let a_mont = MontFp::<Bn254Fr>::from_canonical(a.0);
+        let unreduced =
+            MontFp::from_montgomery((U256(a_mont.to_montgomery()) + U256(Bn254Fr::P)).0);
+        assert_ne!(unreduced.to_montgomery(), a_mont.to_montgomery());
+        assert_eq!(unreduced, a_mont);
+        assert_eq!(unreduced.reduce().to_montgomery(), a_mont.to_montgomery());
+    }
+
+    #[test]
+    fn test_block_multiplier_output() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let a = MontFp::<Bn254Fr>::from_canonical(random_below(&mut rng, Bn254Fr::P));
+        let b = MontFp::<Bn254Fr>::from_canonical(random_below(&mut rng, Bn254Fr::P));
+        let (s0, v0, v1) = crate::block_multiplier::<Bn254Fr>(
+            a.to_montgomery(),
+            b.to_montgomery(),
+            a.to_montgomery(),
+            b.to_montgomery(),
+            a.to_montgomery(),
+            b.to_montgomery(),
+        );
+        for out in [s0, v0, v1] {
+            assert_eq!(MontFp::from_montgomery(out), a * b);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Multiplication modulo the Goldilocks prime P = 2^64 - 2^32 + 1 on the scalar and vector units.
+//!
+//! The 128 bit product reduces with 2^64 ≡ 2^32 - 1 and 2^96 ≡ -1 to a subtraction, a 32x32 bit
+//! multiplication and an addition. The vector lanes build the 128 bit product from four 32x32 bit
+//! products, which every SIMD instruction set has, and need neither floating point nor a rounding
+//! mode.
+//!
+//! Elements are in canonical form. `montgomery_reduction::small_field::goldilocks` in the
+//! experiments crate multiplies in Montgomery form instead, on top of [`widening_mul_simd`].
+
+use std::simd::Simd;
+use std::simd::Select;
+use std::simd::cmp::SimdPartialOrd;
+
+/// Modulus
+pub const P: u64 = 0xffffffff00000001;
+
+/// 2^64 mod P
+const EPSILON: u64 = 0xffffffff;
+
+const MASK32: u64 = 0xffffffff;
+
+/// Multiplication modulo P of `S` pairs on the scalar unit and `V` pairs on the vector unit, the
+/// blocking of [`crate::lanes::block_multiplier`] for single limb elements.
+///
+/// Inputs are any 64 bit values, outputs are fully reduced below P. `V` has to be a lane count
+/// supported by [`std::simd::Simd`]. On x86_64 AVX2 is detected at runtime.
+pub fn block_multiplier<const S: usize, const V: usize>(
+    s_a: [u64; S],
+    s_b: [u64; S],
+    v_a: [u64; V],
+    v_b: [u64; V],
+) -> ([u64; S], [u64; V]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // Safety: the required target features are available
+        return unsafe { block_multiplier_avx2::<S, V>(s_a, s_b, v_a, v_b) };
+    }
+
+    block_multiplier_impl::<S, V>(s_a, s_b, v_a, v_b)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn block_multiplier_avx2<const S: usize, const V: usize>(
+    s_a: [u64; S],
+    s_b: [u64; S],
+    v_a: [u64; V],
+    v_b: [u64; V],
+) -> ([u64; S], [u64; V]) {
+    block_multiplier_impl::<S, V>(s_a, s_b, v_a, v_b)
+}
+
+#[inline(always)]
+fn block_multiplier_impl<const S: usize, const V: usize>(
+    s_a: [u64; S],
+    s_b: [u64; S],
+    v_a: [u64; V],
+    v_b: [u64; V],
+) -> ([u64; S], [u64; V]) {
+    // -- [VECTOR] ---------------------------------------------------------------------------------
+    let v = mul_simd(Simd::from_array(v_a), Simd::from_array(v_b)).to_array();
+    // ---------------------------------------------------------------------------------------------
+    // -- [SCALAR] ---------------------------------------------------------------------------------
+    let s = std::array::from_fn(|i| scalar_mul(s_a[i], s_b[i]));
+    // ---------------------------------------------------------------------------------------------
+    (s, v)
+}
+
+/// The scalar lane of [`block_multiplier`]
+#[inline(always)]
+fn scalar_mul(a: u64, b: u64) -> u64 {
+    let t = a as u128 * b as u128;
+    let (lo, hi) = (t as u64, (t >> 64) as u64);
+
+    // lo - hi_hi, a borrow wraps around 2^64 ≡ EPSILON too many
+    let (t0, borrow) = lo.overflowing_sub(hi >> 32);
+    let t0 = if borrow { t0 - EPSILON } else { t0 };
+    // + hi_lo (2^32 - 1), a carry of 2^64 ≡ EPSILON can't carry again
+    let (t1, carry) = t0.overflowing_add((hi & MASK32) * EPSILON);
+    let t1 = if carry { t1 + EPSILON } else { t1 };
+
+    if t1 >= P { t1 - P } else { t1 }
+}
+
+/// The vector lanes of [`block_multiplier`], [`scalar_mul`] with the carries from comparisons
+#[inline(always)]
+fn mul_simd<const L: usize>(a: Simd<u64, L>, b: Simd<u64, L>) -> Simd<u64, L> {
+    let mask32 = Simd::splat(MASK32);
+    let epsilon = Simd::splat(EPSILON);
+    let (lo, hi) = widening_mul_simd(a, b);
+
+    let hi_hi = hi >> 32;
+    let t0 = lo - hi_hi;
+    let t0 = lo.simd_lt(hi_hi).select(t0 - epsilon, t0);
+    let t1 = t0 + (hi & mask32) * epsilon;
+    let t1 = t1.simd_lt(t0).select(t1 + epsilon, t1);
+
+    let p = Simd::splat(P);
+    t1.simd_ge(p).select(t1 - p, t1)
+}
+
+/// The lower and upper halves of the 128 bit products of every lane, built from 32x32 bit products
+#[inline(always)]
+pub fn widening_mul_simd<const L: usize>(
+    a: Simd<u64, L>,
+    b: Simd<u64, L>,
+) -> (Simd<u64, L>, Simd<u64, L>) {
+    let mask32 = Simd::splat(MASK32);
+
+    // The middle column can't overflow
+    let (a_lo, a_hi) = (a & mask32, a >> 32);
+    let (b_lo, b_hi) = (b & mask32, b >> 32);
+    let ll = a_lo * b_lo;
+    let lh = a_lo * b_hi;
+    let hl = a_hi * b_lo;
+    let hh = a_hi * b_hi;
+    let mid = (ll >> 32) + (lh & mask32) + (hl & mask32);
+    let lo = (ll & mask32) | (mid << 32);
+    let hi = hh + (lh >> 32) + (hl >> 32) + (mid >> 32);
+    (lo, hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{P, block_multiplier};
+    use num_bigint::BigUint;
+    use rand::{Rng, SeedableRng, rngs};
+
+    fn check_block_multiplier<const S: usize, const V: usize>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = BigUint::from(P);
+        let edge = [
+            0,
+            1,
+            2,
+            P - 1,
+            P,
+            P + 1,
+            1 << 32,
+            1 << 63,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+
+        let mut pairs: Vec<(u64, u64)> = (0..10000).map(|_| (rng.random(), rng.random())).collect();
+        for a in edge {
+            for b in edge {
+                pairs.push((a, b));
+            }
+        }
+
+        for chunk in pairs.chunks(S + V) {
+            let a: [u64; S] = std::array::from_fn(|i| chunk[i % chunk.len()].0);
+            let b: [u64; S] = std::array::from_fn(|i| chunk[i % chunk.len()].1);
+            let v_a: [u64; V] = std::array::from_fn(|i| chunk[(S + i) % chunk.len()].0);
+            let v_b: [u64; V] = std::array::from_fn(|i| chunk[(S + i) % chunk.len()].1);
+            let (s, v) = block_multiplier::<S, V>(a, b, v_a, v_b);
+            for (out, (a, b)) in s
+                .into_iter()
+                .chain(v)
+                .zip(a.into_iter().chain(v_a).zip(b.into_iter().chain(v_b)))
+            {
+                let expected = BigUint::from(a) * BigUint::from(b) % &p;
+                assert_eq!(BigUint::from(out), expected, "{a:#x} * {b:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_multiplier() {
+        check_block_multiplier::<1, 2>();
+        check_block_multiplier::<1, 4>();
+        check_block_multiplier::<2, 4>();
+        check_block_multiplier::<0, 8>();
+    }
+}
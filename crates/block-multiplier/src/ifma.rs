@@ -0,0 +1,158 @@
+//! AVX-512 IFMA variant of the vector lane of [`crate::block_multiplier`].
+//!
+//! `vpmadd52luq`/`vpmadd52huq` compute the low and high 52 bits of a 52x52 bit product natively.
+//! This removes the need for the floating point trick with its `make_initial` bias and the switch
+//! to round to zero, and doubles the lane count to 8 by using the full 512 bit registers.
+
+use crate::block_multiplier;
+use crate::config::FieldConfig;
+#[cfg(target_arch = "x86_64")]
+use crate::{
+    addv_simd, constants::MASK52, derivation, resolve_simd_add_truncate, transpose_simd_to_u256,
+    transpose_u256_to_simd, u256_to_u260_shl2_simd, u260_to_u256_simd,
+};
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::{_mm512_madd52hi_epu64, _mm512_madd52lo_epu64};
+#[cfg(target_arch = "x86_64")]
+use std::simd::Simd;
+
+pub const LANES: usize = 8;
+
+/// Montgomery multiplication of eight independent pairs using AVX-512 IFMA.
+///
+/// Inputs, outputs and their bounds are the same as for the vector lanes of
+/// [`block_multiplier`], and so are the results bit for bit.
+///
+/// IFMA is detected at runtime. Without it the pairs are multiplied on the vector lanes of
+/// [`block_multiplier`] instead.
+pub fn block_multiplier_ifma<F: FieldConfig>(
+    a: [[u64; 4]; LANES],
+    b: [[u64; 4]; LANES],
+) -> [[u64; 4]; LANES] {
+    if ifma_available() {
+        // Safety: the required target features are available
+        #[cfg(target_arch = "x86_64")]
+        return unsafe { block_multiplier_avx512ifma::<F>(a, b) };
+    }
+
+    // The scalar lane repeats the first pair of each block, its result isn't used
+    let mut out = [[0; 4]; LANES];
+    for i in (0..LANES).step_by(2) {
+        let (_, v0, v1) = block_multiplier::<F>(a[i], b[i], a[i], b[i], a[i + 1], b[i + 1]);
+        out[i] = v0;
+        out[i + 1] = v1;
+    }
+    out
+}
+
+#[cfg(target_arch = "x86_64")]
+fn ifma_available() -> bool {
+    is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512ifma")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn ifma_available() -> bool {
+    false
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512ifma")]
+unsafe fn block_multiplier_avx512ifma<F: FieldConfig>(
+    a: [[u64; 4]; LANES],
+    b: [[u64; 4]; LANES],
+) -> [[u64; 4]; LANES] {
+    const { derivation::assert_modulus(F::P) };
+
+    let a = u256_to_u260_shl2_simd(transpose_u256_to_simd(a));
+    let b = u256_to_u260_shl2_simd(transpose_u256_to_simd(b));
+
+    let mut t: [Simd<u64, LANES>; 10] = [Simd::splat(0); 10];
+    for i in 0..a.len() {
+        for j in 0..b.len() {
+            t[i + j + 1] = madd52hi(t[i + j + 1], a[i], b[j]);
+            t[i + j] = madd52lo(t[i + j], a[i], b[j]);
+        }
+    }
+
+    t[1] += t[0] >> 52;
+    t[2] += t[1] >> 52;
+    t[3] += t[2] >> 52;
+    t[4] += t[3] >> 52;
+
+    let r0 = smult_noinit_ifma(t[0] & Simd::splat(MASK52), F::RHO_4);
+    let r1 = smult_noinit_ifma(t[1] & Simd::splat(MASK52), F::RHO_3);
+    let r2 = smult_noinit_ifma(t[2] & Simd::splat(MASK52), F::RHO_2);
+    let r3 = smult_noinit_ifma(t[3] & Simd::splat(MASK52), F::RHO_1);
+
+    let s = [t[4], t[5], t[6], t[7], t[8], t[9]];
+
+    let s = addv_simd(r3, addv_simd(addv_simd(s, r0), addv_simd(r1, r2)));
+
+    // Only the low 52 bits of s[0] contribute to the low 52 bits of the product
+    let m = madd52lo(Simd::splat(0), s[0], Simd::splat(F::U52_NP0));
+    let mp = smult_noinit_ifma(m, F::U52_P);
+
+    let resolve = resolve_simd_add_truncate(s, mp);
+    transpose_simd_to_u256(u260_to_u256_simd(resolve))
+}
+
+/// acc + the low 52 bits of a * b, for a, b < 2^52
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512ifma")]
+#[inline]
+fn madd52lo(acc: Simd<u64, LANES>, a: Simd<u64, LANES>, b: Simd<u64, LANES>) -> Simd<u64, LANES> {
+    _mm512_madd52lo_epu64(acc.into(), a.into(), b.into()).into()
+}
+
+/// acc + the high 52 bits of a * b, for a, b < 2^52
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512ifma")]
+#[inline]
+fn madd52hi(acc: Simd<u64, LANES>, a: Simd<u64, LANES>, b: Simd<u64, LANES>) -> Simd<u64, LANES> {
+    _mm512_madd52hi_epu64(acc.into(), a.into(), b.into()).into()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512ifma")]
+#[inline]
+fn smult_noinit_ifma(s: Simd<u64, LANES>, v: [u64; 5]) -> [Simd<u64, LANES>; 6] {
+    let mut t = [Simd::splat(0); 6];
+    for i in 0..v.len() {
+        t[i + 1] = madd52hi(t[i + 1], s, Simd::splat(v[i]));
+        t[i] = madd52lo(t[i], s, Simd::splat(v[i]));
+    }
+    t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bn254Fq, Bn254Fr};
+    use crate::test_utils::random_below;
+    use rand::{SeedableRng, rngs};
+
+    // Any value below P is a valid Montgomery form, so no conversion is needed to compare
+    fn check_ifma_matches_vector_lanes<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for _ in 0..10000 {
+            let a: [[u64; 4]; LANES] = std::array::from_fn(|_| random_below(&mut rng, F::P));
+            let b: [[u64; 4]; LANES] = std::array::from_fn(|_| random_below(&mut rng, F::P));
+            let out = block_multiplier_ifma::<F>(a, b);
+            for i in (0..LANES).step_by(2) {
+                let (_, v0, v1) = block_multiplier::<F>(a[i], b[i], a[i], b[i], a[i + 1], b[i + 1]);
+                assert_eq!(out[i], v0);
+                assert_eq!(out[i + 1], v1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_multiplier_ifma() {
+        check_ifma_matches_vector_lanes::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_block_multiplier_ifma_bn254_fq() {
+        check_ifma_matches_vector_lanes::<Bn254Fq>();
+    }
+}
@@ -0,0 +1,231 @@
+//! Field inversion and Montgomery's batch inversion trick on top of [`block_multiplier`].
+//!
+//! Inputs are in Montgomery form below [`FieldConfig::OUTPUT_MAX`], like those of [`crate::arith`],
+//! and the inverses are returned in Montgomery form below P.
+
+use crate::arith::reduce;
+use crate::block_multiplier;
+use crate::config::FieldConfig;
+use crate::derivation::{add, half_mod, sub};
+use crate::{scalar_mul, scalar_sqr};
+
+/// Inverse with the binary extended Euclidean algorithm, or `None` for zero.
+///
+/// The running time depends on the input, see [`inv_ct`] for secret values.
+pub fn inv<F: FieldConfig>(a: [u64; 4]) -> Option<[u64; 4]> {
+    let a = reduce::<F>(a);
+    if a == [0; 4] {
+        return None;
+    }
+
+    // Invariants: x1 * a = u and x2 * a = v mod P, gcd(u, v) = 1
+    let (mut u, mut v) = (a, F::P);
+    let (mut x1, mut x2) = ([1, 0, 0, 0], [0; 4]);
+    while u != [1, 0, 0, 0] && v != [1, 0, 0, 0] {
+        while u[0] & 1 == 0 {
+            u = shr1(u);
+            x1 = half_mod(x1, F::P);
+        }
+        while v[0] & 1 == 0 {
+            v = shr1(v);
+            x2 = half_mod(x2, F::P);
+        }
+        let (diff, borrow) = sub(u, v);
+        if borrow {
+            v = sub(v, u).0;
+            x2 = sub_mod::<F>(x2, x1);
+        } else {
+            u = diff;
+            x1 = sub_mod::<F>(x1, x2);
+        }
+    }
+    let a_inv = if u == [1, 0, 0, 0] { x1 } else { x2 };
+
+    // (aR)^-1 to a^-1 R
+    Some(reduce::<F>(scalar_mul::<F>(a_inv, F::R3)))
+}
+
+/// Inverse as a^(P - 2), zero for zero.
+///
+/// The exponent is fixed, so the sequence of operations doesn't depend on the input.
+pub fn inv_ct<F: FieldConfig>(a: [u64; 4]) -> [u64; 4] {
+    let exponent = const { sub(F::P, [2, 0, 0, 0]).0 };
+    let a = reduce::<F>(a);
+
+    let mut x = F::R;
+    for i in (0..256).rev() {
+        x = reduce::<F>(scalar_sqr::<F>(x));
+        if (exponent[i / 64] >> (i % 64)) & 1 == 1 {
+            x = reduce::<F>(scalar_mul::<F>(x, a));
+        }
+    }
+    x
+}
+
+/// Element wise inverse `out[i] = values[i]^-1`, with zero for zero.
+///
+/// Montgomery's trick replaces all inversions but one by three multiplications each. The elements
+/// are split into three interleaved chains, `values[3k + j]` belonging to chain `j`, which are
+/// multiplied in lockstep on the three lanes of [`block_multiplier`]. This costs three inversions
+/// with [`inv`] in total.
+///
+/// The running time depends on the values. Panics if the slices don't have the same length.
+pub fn batch_inv<F: FieldConfig>(values: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    assert_eq!(values.len(), out.len(), "output length differs");
+
+    let n = values.len();
+    let rows = n.div_ceil(3);
+    // Zeros are skipped by treating them as one, missing elements of the last row too
+    let element = |i: usize| {
+        let value = if i < n {
+            reduce::<F>(values[i])
+        } else {
+            [0; 4]
+        };
+        if value == [0; 4] { F::R } else { value }
+    };
+
+    // out[i] holds the product of the elements of its chain up to and including i
+    let mut acc = [F::R; 3];
+    for (k, out) in out.chunks_mut(3).enumerate() {
+        let e: [[u64; 4]; 3] = std::array::from_fn(|j| element(3 * k + j));
+        let (p0, p1, p2) = block_multiplier::<F>(acc[0], e[0], acc[1], e[1], acc[2], e[2]);
+        acc = [p0, p1, p2].map(reduce::<F>);
+        out.copy_from_slice(&acc[..out.len()]);
+    }
+
+    // All chain products are non zero
+    let mut acc_inv = acc.map(|acc| inv::<F>(acc).unwrap());
+    for k in (0..rows).rev() {
+        let e: [[u64; 4]; 3] = std::array::from_fn(|j| element(3 * k + j));
+        let prev: [[u64; 4]; 3] =
+            std::array::from_fn(|j| if k > 0 { out[3 * (k - 1) + j] } else { F::R });
+
+        // The inverse of the element is the inverse of the chain product up to it times the
+        // product of the preceding elements, the inverse of the preceding product follows from
+        // multiplying with the element.
+        let (i0, i1, i2) = block_multiplier::<F>(
+            acc_inv[0], prev[0], acc_inv[1], prev[1], acc_inv[2], prev[2],
+        );
+        let (n0, n1, n2) =
+            block_multiplier::<F>(acc_inv[0], e[0], acc_inv[1], e[1], acc_inv[2], e[2]);
+
+        let inverses = [i0, i1, i2].map(reduce::<F>);
+        for (i, inverse) in (3 * k..n).zip(inverses) {
+            out[i] = if reduce::<F>(values[i]) == [0; 4] {
+                [0; 4]
+            } else {
+                inverse
+            };
+        }
+        acc_inv = [n0, n1, n2].map(reduce::<F>);
+    }
+}
+
+/// (a - b) mod P for a, b < P
+#[inline(always)]
+fn sub_mod<F: FieldConfig>(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    let (diff, borrow) = sub(a, b);
+    if borrow { add(diff, F::P).0 } else { diff }
+}
+
+#[inline(always)]
+fn shr1(a: [u64; 4]) -> [u64; 4] {
+    [
+        (a[0] >> 1) | (a[1] << 63),
+        (a[1] >> 1) | (a[2] << 63),
+        (a[2] >> 1) | (a[3] << 63),
+        a[3] >> 1,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bn254Fq, Bn254Fr};
+    use crate::test_utils::{mod_mul, random_below};
+    use primitive_types::U256;
+    use rand::{Rng, SeedableRng, rngs};
+
+    /// a^-1 mod P as a^(P - 2) by square and multiply on U256
+    fn mod_inv(a: U256, p: U256) -> U256 {
+        let exponent = p - 2;
+        let mut x = U256::one();
+        for i in (0..256).rev() {
+            x = mod_mul(x, x, p);
+            if exponent.bit(i) {
+                x = mod_mul(x, a, p);
+            }
+        }
+        x
+    }
+
+    fn check_inv<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = U256(F::P);
+        let r = U256(F::R);
+
+        let mut inputs = vec![U256::one(), p - 1];
+        inputs.extend((0..1000).map(|_| U256(random_below(&mut rng, F::P))));
+        for a in inputs {
+            let a_mont = mod_mul(a, r, p);
+            let expected = mod_mul(mod_inv(a, p), r, p);
+            assert_eq!(inv::<F>(a_mont.0).map(U256), Some(expected));
+            assert_eq!(U256(inv_ct::<F>(a_mont.0)), expected);
+            // Partially reduced representatives have the same inverse
+            let unreduced = a_mont + p;
+            assert_eq!(inv::<F>(unreduced.0).map(U256), Some(expected));
+            assert_eq!(U256(inv_ct::<F>(unreduced.0)), expected);
+        }
+
+        assert_eq!(inv::<F>([0; 4]), None);
+        assert_eq!(inv::<F>(F::P), None);
+        assert_eq!(inv_ct::<F>([0; 4]), [0; 4]);
+    }
+
+    #[test]
+    fn test_inv() {
+        check_inv::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_inv_bn254_fq() {
+        check_inv::<Bn254Fq>();
+    }
+
+    fn check_batch_inv<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for len in (0..20).chain([1000, 1001, 1002]) {
+            let mut values: Vec<[u64; 4]> =
+                (0..len).map(|_| random_below(&mut rng, F::P)).collect();
+            // Zeros in all chains
+            for i in [0, 4, 8] {
+                if i < len && rng.random_bool(0.5) {
+                    values[i] = [0; 4];
+                }
+            }
+            let mut out = vec![[0; 4]; len];
+            batch_inv::<F>(&values, &mut out);
+
+            for (value, out) in values.iter().zip(&out) {
+                assert_eq!(*out, inv::<F>(*value).unwrap_or([0; 4]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_inv() {
+        check_batch_inv::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_batch_inv_bn254_fq() {
+        check_batch_inv::<Bn254Fq>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_batch_inv_length_mismatch() {
+        batch_inv::<Bn254Fr>(&[[0; 4]; 3], &mut [[0; 4]; 2]);
+    }
+}
@@ -0,0 +1,172 @@
+//! [`crate::block_multiplier`] with a configurable number of scalar and vector multiplications.
+//!
+//! The split of one scalar and two vector multiplications per call keeps both units of the core
+//! it was tuned on busy, other cores balance differently. [`block_multiplier`] builds `S` scalar
+//! and `V` vector multiplications from the same building blocks, such that the best mix can be
+//! picked per CPU with the `block_multiplier_lanes` benchmarks. With `V = 4` every limb occupies
+//! two NEON registers or one AVX2 register.
+
+use crate::config::FieldConfig;
+use crate::derivation;
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use crate::{
+    mul_u260_simd, rounding, rounding::RoundToZeroGuard, transpose_simd_to_u256,
+    transpose_u256_to_simd, u256_to_u260_shl2_simd, u260_to_u256_simd,
+};
+use crate::{scalar, scalar_mul};
+
+/// Montgomery multiplication of `S` pairs on the scalar unit and `V` pairs on the vector unit.
+///
+/// Every product is bit for bit the one of the same lane of [`crate::block_multiplier`], so the
+/// same bounds apply. `V` has to be a lane count supported by [`std::simd::Simd`], which excludes
+/// zero.
+pub fn block_multiplier<F: FieldConfig, const S: usize, const V: usize>(
+    s_a: [[u64; 4]; S],
+    s_b: [[u64; 4]; S],
+    v_a: [[u64; 4]; V],
+    v_b: [[u64; 4]; V],
+) -> ([[u64; 4]; S], [[u64; 4]; V]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { block_multiplier_avx2::<F, S, V>(s_a, s_b, v_a, v_b) };
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        block_multiplier_impl::<F, S, V>(s_a, s_b, v_a, v_b)
+    }
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    {
+        block_multiplier_scalar::<F, S, V>(s_a, s_b, v_a, v_b)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn block_multiplier_avx2<F: FieldConfig, const S: usize, const V: usize>(
+    s_a: [[u64; 4]; S],
+    s_b: [[u64; 4]; S],
+    v_a: [[u64; 4]; V],
+    v_b: [[u64; 4]; V],
+) -> ([[u64; 4]; S], [[u64; 4]; V]) {
+    block_multiplier_impl::<F, S, V>(s_a, s_b, v_a, v_b)
+}
+
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn block_multiplier_impl<F: FieldConfig, const S: usize, const V: usize>(
+    s_a: [[u64; 4]; S],
+    s_b: [[u64; 4]; S],
+    v_a: [[u64; 4]; V],
+    v_b: [[u64; 4]; V],
+) -> ([[u64; 4]; S], [[u64; 4]; V]) {
+    let guard = RoundToZeroGuard::new();
+    let out = block_multiplier_rtz::<F, S, V>(s_a, s_b, v_a, v_b);
+    drop(guard);
+    out
+}
+
+/// [`block_multiplier`] without switching the rounding mode, which has to be round to zero already
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn block_multiplier_rtz<F: FieldConfig, const S: usize, const V: usize>(
+    s_a: [[u64; 4]; S],
+    s_b: [[u64; 4]; S],
+    v_a: [[u64; 4]; V],
+    v_b: [[u64; 4]; V],
+) -> ([[u64; 4]; S], [[u64; 4]; V]) {
+    const { derivation::assert_modulus(F::P) };
+    rounding::assert_round_to_zero();
+
+    // -- [VECTOR] ---------------------------------------------------------------------------------
+    // See crate::block_multiplier_rtz for the black_box
+    let [v_a, v_b] = std::hint::black_box([v_a, v_b]);
+    let v_a = u256_to_u260_shl2_simd(transpose_u256_to_simd(v_a));
+    let v_b = u256_to_u260_shl2_simd(transpose_u256_to_simd(v_b));
+    let u260_result = mul_u260_simd::<F, V>(v_a, v_b);
+    let v = std::hint::black_box(transpose_simd_to_u256(u260_to_u256_simd(u260_result)));
+    // ---------------------------------------------------------------------------------------------
+    // -- [SCALAR] ---------------------------------------------------------------------------------
+    let s = std::array::from_fn(|i| scalar_mul::<F>(s_a[i], s_b[i]));
+    // ---------------------------------------------------------------------------------------------
+    (s, v)
+}
+
+#[cfg_attr(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    allow(dead_code)
+)]
+fn block_multiplier_scalar<F: FieldConfig, const S: usize, const V: usize>(
+    s_a: [[u64; 4]; S],
+    s_b: [[u64; 4]; S],
+    v_a: [[u64; 4]; V],
+    v_b: [[u64; 4]; V],
+) -> ([[u64; 4]; S], [[u64; 4]; V]) {
+    const { derivation::assert_modulus(F::P) };
+
+    let s = std::array::from_fn(|i| scalar_mul::<F>(s_a[i], s_b[i]));
+    let v = std::array::from_fn(|i| scalar::vector_lane_mul::<F>(v_a[i], v_b[i]));
+    (s, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bn254Fq, Bn254Fr};
+    use rand::{Rng, SeedableRng, rngs};
+
+    fn check_lanes<F: FieldConfig, const S: usize, const V: usize>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let s_a: [[u64; 4]; S] = rng.random();
+            let s_b: [[u64; 4]; S] = rng.random();
+            let v_a: [[u64; 4]; V] = rng.random();
+            let v_b: [[u64; 4]; V] = rng.random();
+
+            let expected = block_multiplier_scalar::<F, S, V>(s_a, s_b, v_a, v_b);
+            assert_eq!(block_multiplier::<F, S, V>(s_a, s_b, v_a, v_b), expected);
+
+            // The lanes of the default split agree
+            let (s0, v0, v1) =
+                crate::block_multiplier::<F>(s_a[0], s_b[0], v_a[0], v_b[0], v_a[1], v_b[1]);
+            assert_eq!((s0, v0, v1), (expected.0[0], expected.1[0], expected.1[1]));
+        }
+    }
+
+    #[test]
+    fn test_lanes() {
+        check_lanes::<Bn254Fr, 1, 2>();
+        check_lanes::<Bn254Fr, 2, 2>();
+        check_lanes::<Bn254Fr, 1, 4>();
+        check_lanes::<Bn254Fr, 2, 8>();
+    }
+
+    #[test]
+    fn test_lanes_bn254_fq() {
+        check_lanes::<Bn254Fq, 1, 2>();
+        check_lanes::<Bn254Fq, 3, 4>();
+    }
+
+    #[test]
+    fn test_no_scalar_lanes() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let v_a: [[u64; 4]; 4] = rng.random();
+            let v_b: [[u64; 4]; 4] = rng.random();
+            let (_, v) = block_multiplier::<Bn254Fr, 0, 4>([], [], v_a, v_b);
+            for i in 0..4 {
+                assert_eq!(v[i], scalar::vector_lane_mul::<Bn254Fr>(v_a[i], v_b[i]));
+            }
+        }
+    }
+}
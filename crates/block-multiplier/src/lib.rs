@@ -1,12 +1,48 @@
 #![feature(portable_simd)]
 
+use crate::config::FieldConfig;
 use crate::constants::*;
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use crate::rounding::RoundToZeroGuard;
 use seq_macro::seq;
-use std::arch::aarch64::vcvtq_f64_u64;
 use std::ops::BitAnd;
-use std::simd::{Simd, StdFloat, num::SimdFloat};
-
+use std::simd::Simd;
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+use std::simd::num::SimdUint;
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use std::simd::{StdFloat, num::SimdFloat};
+
+pub mod arith;
+pub mod batch;
+pub mod config;
 pub mod constants;
+pub mod derivation;
+pub mod field;
+pub mod goldilocks;
+pub mod ifma;
+pub mod inv;
+pub mod lanes;
+#[cfg(all(
+    feature = "rayon",
+    any(target_arch = "aarch64", target_arch = "x86_64")
+))]
+pub mod pool;
+pub mod pow;
+pub mod pseudo_mersenne;
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+pub mod rounding;
+pub mod scalar;
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+pub mod u52;
 
 /// Macro to extract a subarray from an array.
 ///
@@ -44,7 +80,20 @@ macro_rules! subarray {
     };
 }
 
-pub fn block_multiplier(
+/// Montgomery multiplication of three independent pairs: one on the scalar (64 bit limb) unit and
+/// two on the vector (52 bit limb, floating point) unit.
+///
+/// Inputs and outputs are in Montgomery form for the field `F`. The outputs are not fully reduced:
+/// for inputs below P they are below [`FieldConfig::OUTPUT_MAX`]. The functions in [`arith`] take
+/// such outputs and return values below P again. Chains of multiplications on the vector unit can
+/// skip the conversion between the limb sizes, see the `u52` module.
+///
+/// Fails to compile for fields whose modulus doesn't leave the required headroom, see
+/// [`FieldConfig`].
+///
+/// On x86_64 the vector unit requires FMA, which is detected at runtime. Targets without the
+/// required SIMD support use the bit for bit identical [`scalar::block_multiplier`].
+pub fn block_multiplier<F: FieldConfig>(
     s0_a: [u64; 4],
     s0_b: [u64; 4],
     v0_a: [u64; 4],
@@ -52,177 +101,376 @@ pub fn block_multiplier(
     v1_a: [u64; 4],
     v1_b: [u64; 4],
 ) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { block_multiplier_avx2::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b) };
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        block_multiplier_impl::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b)
+    }
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    {
+        scalar::block_multiplier::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b)
+    }
+}
+
+/// [`block_multiplier`] compiled with AVX2 and FMA such that `mul_add` lowers to `vfmadd`
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn block_multiplier_avx2<F: FieldConfig>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    block_multiplier_impl::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b)
+}
+
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn block_multiplier_impl<F: FieldConfig>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    let guard = RoundToZeroGuard::new();
+    let out = block_multiplier_rtz::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b);
+    drop(guard);
+    out
+}
+
+/// [`block_multiplier`] without switching the rounding mode, which has to be round to zero already
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn block_multiplier_rtz<F: FieldConfig>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    const { derivation::assert_modulus(F::P) };
+    rounding::assert_round_to_zero();
+
     // -- [VECTOR] ---------------------------------------------------------------------------------
-    let fpcr = set_round_to_zero();
+    // Floating point operations don't depend on the rounding mode as far as the compiler is
+    // concerned. Passing the inputs and outputs through black_box keeps them from being moved
+    // outside of the region where the caller has set round to zero.
+    let [v0_a, v0_b, v1_a, v1_b] = std::hint::black_box([v0_a, v0_b, v1_a, v1_b]);
     let v0_a = u256_to_u260_shl2_simd(transpose_u256_to_simd([v0_a, v1_a]));
     let v0_b = u256_to_u260_shl2_simd(transpose_u256_to_simd([v0_b, v1_b]));
 
-    let mut t: [Simd<u64, 2>; 10] = [Simd::splat(0); 10];
-    t[0] = Simd::splat(make_initial(1, 0));
-    t[9] = Simd::splat(make_initial(0, 6));
-    t[1] = Simd::splat(make_initial(2, 1));
-    t[8] = Simd::splat(make_initial(6, 7));
-    t[2] = Simd::splat(make_initial(3, 2));
-    t[7] = Simd::splat(make_initial(7, 8));
-    t[3] = Simd::splat(make_initial(4, 3));
-    t[6] = Simd::splat(make_initial(8, 9));
-    t[4] = Simd::splat(make_initial(10, 4));
-    t[5] = Simd::splat(make_initial(9, 10));
+    let u260_result = mul_u260_simd::<F, 2>(v0_a, v0_b);
+    let v = std::hint::black_box(transpose_simd_to_u256(u260_to_u256_simd(u260_result)));
+    // ---------------------------------------------------------------------------------------------
+    // -- [SCALAR] ---------------------------------------------------------------------------------
+    let s0 = scalar_mul::<F>(s0_a, s0_b);
+    // ---------------------------------------------------------------------------------------------
+    (s0, v[0], v[1])
+}
+
+/// Montgomery squaring of three independent elements, laid out like [`block_multiplier`].
+///
+/// The symmetric partial products are computed once and doubled, 15 instead of 25 in the vector
+/// lanes and 10 instead of 16 in the scalar lane. The results are bit for bit those of
+/// [`block_multiplier`] with both operands equal, so the same output bound applies.
+pub fn block_squarer<F: FieldConfig>(
+    s0_a: [u64; 4],
+    v0_a: [u64; 4],
+    v1_a: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { block_squarer_avx2::<F>(s0_a, v0_a, v1_a) };
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        let guard = RoundToZeroGuard::new();
+        let out = block_squarer_rtz::<F>(s0_a, v0_a, v1_a);
+        drop(guard);
+        out
+    }
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    {
+        scalar::block_squarer::<F>(s0_a, v0_a, v1_a)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn block_squarer_avx2<F: FieldConfig>(
+    s0_a: [u64; 4],
+    v0_a: [u64; 4],
+    v1_a: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    let guard = RoundToZeroGuard::new();
+    let out = block_squarer_rtz::<F>(s0_a, v0_a, v1_a);
+    drop(guard);
+    out
+}
+
+/// [`block_squarer`] without switching the rounding mode, which has to be round to zero already
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn block_squarer_rtz<F: FieldConfig>(
+    s0_a: [u64; 4],
+    v0_a: [u64; 4],
+    v1_a: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    const { derivation::assert_modulus(F::P) };
+    rounding::assert_round_to_zero();
+
+    // -- [VECTOR] ---------------------------------------------------------------------------------
+    // See block_multiplier_rtz for the black_box
+    let [v0_a, v1_a] = std::hint::black_box([v0_a, v1_a]);
+    let v0_a = u256_to_u260_shl2_simd(transpose_u256_to_simd([v0_a, v1_a]));
+    let av = v0_a.map(u52_to_f64_simd);
+
+    // Every product is doubled by shifting the floating point bits which doubles the exponent
+    // bits as well. That matches the two products of the general multiplication, so the initial
+    // values are the same.
+    let mut t = initial_t_simd();
+    for i in 0..av.len() {
+        let p_hi = av[i].mul_add(av[i], Simd::splat(C1));
+        let p_lo = av[i].mul_add(av[i], Simd::splat(C2) - p_hi);
+        t[i + i + 1] += p_hi.to_bits();
+        t[i + i] += p_lo.to_bits();
+        for j in i + 1..av.len() {
+            let p_hi = av[i].mul_add(av[j], Simd::splat(C1));
+            let p_lo = av[i].mul_add(av[j], Simd::splat(C2) - p_hi);
+            t[i + j + 1] += p_hi.to_bits() << 1;
+            t[i + j] += p_lo.to_bits() << 1;
+        }
+    }
+
+    let u256_result = u260_to_u256_simd(reduce_simd::<F, 2>(t));
+    let v = std::hint::black_box(transpose_simd_to_u256(u256_result));
+    // ---------------------------------------------------------------------------------------------
+    // -- [SCALAR] ---------------------------------------------------------------------------------
+    let s0 = scalar_sqr::<F>(s0_a);
+    // ---------------------------------------------------------------------------------------------
+    (s0, v[0], v[1])
+}
 
-    let avi: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_a[0].into()).into() };
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[0].into()).into() };
+/// Montgomery multiplication of the vector lanes in 52 bit limbs, see [`u52`] for the bounds
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn mul_u260_simd<F: FieldConfig, const L: usize>(
+    v0_a: [Simd<u64, L>; 5],
+    v0_b: [Simd<u64, L>; 5],
+) -> [Simd<u64, L>; 5] {
+    let mut t = initial_t_simd();
+
+    let avi: Simd<f64, L> = u52_to_f64_simd(v0_a[0]);
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[0]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[0 + 0 + 1] += p_hi.to_bits();
     t[0 + 0] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[1].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[1]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[0 + 1 + 1] += p_hi.to_bits();
     t[0 + 1] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[2].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[2]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[0 + 2 + 1] += p_hi.to_bits();
     t[0 + 2] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[3].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[3]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[0 + 3 + 1] += p_hi.to_bits();
     t[0 + 3] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[4].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[4]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[0 + 4 + 1] += p_hi.to_bits();
     t[0 + 4] += p_lo.to_bits();
-    let avi: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_a[1].into()).into() };
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[0].into()).into() };
+    let avi: Simd<f64, L> = u52_to_f64_simd(v0_a[1]);
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[0]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[1 + 0 + 1] += p_hi.to_bits();
     t[1 + 0] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[1].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[1]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[1 + 1 + 1] += p_hi.to_bits();
     t[1 + 1] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[2].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[2]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[1 + 2 + 1] += p_hi.to_bits();
     t[1 + 2] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[3].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[3]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[1 + 3 + 1] += p_hi.to_bits();
     t[1 + 3] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[4].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[4]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[1 + 4 + 1] += p_hi.to_bits();
     t[1 + 4] += p_lo.to_bits();
-    let avi: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_a[2].into()).into() };
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[0].into()).into() };
+    let avi: Simd<f64, L> = u52_to_f64_simd(v0_a[2]);
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[0]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[2 + 0 + 1] += p_hi.to_bits();
     t[2 + 0] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[1].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[1]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[2 + 1 + 1] += p_hi.to_bits();
     t[2 + 1] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[2].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[2]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[2 + 2 + 1] += p_hi.to_bits();
     t[2 + 2] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[3].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[3]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[2 + 3 + 1] += p_hi.to_bits();
     t[2 + 3] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[4].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[4]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[2 + 4 + 1] += p_hi.to_bits();
     t[2 + 4] += p_lo.to_bits();
-    let avi: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_a[3].into()).into() };
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[0].into()).into() };
+    let avi: Simd<f64, L> = u52_to_f64_simd(v0_a[3]);
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[0]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[3 + 0 + 1] += p_hi.to_bits();
     t[3 + 0] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[1].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[1]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[3 + 1 + 1] += p_hi.to_bits();
     t[3 + 1] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[2].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[2]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[3 + 2 + 1] += p_hi.to_bits();
     t[3 + 2] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[3].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[3]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[3 + 3 + 1] += p_hi.to_bits();
     t[3 + 3] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[4].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[4]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[3 + 4 + 1] += p_hi.to_bits();
     t[3 + 4] += p_lo.to_bits();
-    let avi: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_a[4].into()).into() };
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[0].into()).into() };
+    let avi: Simd<f64, L> = u52_to_f64_simd(v0_a[4]);
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[0]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[4 + 0 + 1] += p_hi.to_bits();
     t[4 + 0] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[1].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[1]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[4 + 1 + 1] += p_hi.to_bits();
     t[4 + 1] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[2].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[2]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[4 + 2 + 1] += p_hi.to_bits();
     t[4 + 2] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[3].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[3]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[4 + 3 + 1] += p_hi.to_bits();
     t[4 + 3] += p_lo.to_bits();
-    let bvj: Simd<f64, 2> = unsafe { vcvtq_f64_u64(v0_b[4].into()).into() };
+    let bvj: Simd<f64, L> = u52_to_f64_simd(v0_b[4]);
     let p_hi = avi.mul_add(bvj, Simd::splat(C1));
     let p_lo = avi.mul_add(bvj, Simd::splat(C2) - p_hi);
     t[4 + 4 + 1] += p_hi.to_bits();
     t[4 + 4] += p_lo.to_bits();
 
+    reduce_simd::<F, L>(t)
+}
+
+/// Accumulators of the vector lane. The initial values cancel the exponent bits of every floating
+/// point product that ends up in them, including those added during the reduction.
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn initial_t_simd<const L: usize>() -> [Simd<u64, L>; 10] {
+    let mut t: [Simd<u64, L>; 10] = [Simd::splat(0); 10];
+    t[0] = Simd::splat(make_initial(1, 0));
+    t[9] = Simd::splat(make_initial(0, 6));
+    t[1] = Simd::splat(make_initial(2, 1));
+    t[8] = Simd::splat(make_initial(6, 7));
+    t[2] = Simd::splat(make_initial(3, 2));
+    t[7] = Simd::splat(make_initial(7, 8));
+    t[3] = Simd::splat(make_initial(4, 3));
+    t[6] = Simd::splat(make_initial(8, 9));
+    t[4] = Simd::splat(make_initial(10, 4));
+    t[5] = Simd::splat(make_initial(9, 10));
+    t
+}
+
+/// Montgomery reduction of the 520 bit product of the vector lanes
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn reduce_simd<F: FieldConfig, const L: usize>(mut t: [Simd<u64, L>; 10]) -> [Simd<u64, L>; 5] {
     t[1] += t[0] >> 52;
     t[2] += t[1] >> 52;
     t[3] += t[2] >> 52;
     t[4] += t[3] >> 52;
 
-    let r0 = smult_noinit_simd(t[0].bitand(Simd::splat(MASK52)), RHO_4);
-    let r1 = smult_noinit_simd(t[1].bitand(Simd::splat(MASK52)), RHO_3);
-    let r2 = smult_noinit_simd(t[2].bitand(Simd::splat(MASK52)), RHO_2);
-    let r3 = smult_noinit_simd(t[3].bitand(Simd::splat(MASK52)), RHO_1);
+    let r0 = smult_noinit_simd(t[0].bitand(Simd::splat(MASK52)), F::RHO_4);
+    let r1 = smult_noinit_simd(t[1].bitand(Simd::splat(MASK52)), F::RHO_3);
+    let r2 = smult_noinit_simd(t[2].bitand(Simd::splat(MASK52)), F::RHO_2);
+    let r3 = smult_noinit_simd(t[3].bitand(Simd::splat(MASK52)), F::RHO_1);
 
     let s = [t[4], t[5], t[6], t[7], t[8], t[9]];
 
     let s = addv_simd(r3, addv_simd(addv_simd(s, r0), addv_simd(r1, r2)));
 
-    let m = (s[0] * Simd::splat(U52_NP0)).bitand(Simd::splat(MASK52));
-    let mp = smult_noinit_simd(m, U52_P);
+    let m = (s[0] * Simd::splat(F::U52_NP0)).bitand(Simd::splat(MASK52));
+    let mp = smult_noinit_simd(m, F::U52_P);
 
-    let resolve = resolve_simd_add_truncate(s, mp);
-    let u256_result = u260_to_u256_simd(resolve);
-    let v = transpose_simd_to_u256(u256_result);
-    set_fpcr(fpcr);
-    // ---------------------------------------------------------------------------------------------
-    // -- [SCALAR] ---------------------------------------------------------------------------------
+    resolve_simd_add_truncate(s, mp)
+}
+
+/// The scalar lane of [`block_multiplier`]: Montgomery multiplication in 64 bit limbs with the
+/// lower limbs of the product reduced through the precomputed `U64_I*` tables.
+#[inline(always)]
+fn scalar_mul<F: FieldConfig>(s0_a: [u64; 4], s0_b: [u64; 4]) -> [u64; 4] {
     let mut s0_t = [0_u64; 8];
     let mut carry = 0;
     (s0_t[0], carry) = carrying_mul_add(s0_a[0], s0_b[0], s0_t[0], carry);
@@ -249,36 +497,75 @@ pub fn block_multiplier(
     (s0_t[6], carry) = carrying_mul_add(s0_a[3], s0_b[3], s0_t[6], carry);
     s0_t[7] = carry;
 
+    scalar_reduce::<F>(s0_t)
+}
+
+/// The scalar lane of [`block_squarer`]
+#[inline(always)]
+fn scalar_sqr<F: FieldConfig>(s0_a: [u64; 4]) -> [u64; 4] {
+    // Products below the diagonal
+    let mut s0_t = [0_u64; 8];
+    let mut carry = 0;
+    (s0_t[1], carry) = carrying_mul_add(s0_a[0], s0_a[1], s0_t[1], carry);
+    (s0_t[2], carry) = carrying_mul_add(s0_a[0], s0_a[2], s0_t[2], carry);
+    (s0_t[3], carry) = carrying_mul_add(s0_a[0], s0_a[3], s0_t[3], carry);
+    s0_t[4] = carry;
+    carry = 0;
+    (s0_t[3], carry) = carrying_mul_add(s0_a[1], s0_a[2], s0_t[3], carry);
+    (s0_t[4], carry) = carrying_mul_add(s0_a[1], s0_a[3], s0_t[4], carry);
+    s0_t[5] = carry;
+    (s0_t[5], s0_t[6]) = carrying_mul_add(s0_a[2], s0_a[3], s0_t[5], 0);
+
+    // Double them for the products above the diagonal
+    s0_t[7] = s0_t[6] >> 63;
+    for i in (1..7).rev() {
+        s0_t[i] = (s0_t[i] << 1) | (s0_t[i - 1] >> 63);
+    }
+
+    // Add the diagonal
+    let mut carry = 0;
+    for i in 0..s0_a.len() {
+        let (lo, hi) = carrying_mul_add(s0_a[i], s0_a[i], s0_t[2 * i], carry);
+        let (sum, overflow) = s0_t[2 * i + 1].overflowing_add(hi);
+        s0_t[2 * i] = lo;
+        s0_t[2 * i + 1] = sum;
+        carry = overflow as u64;
+    }
+
+    scalar_reduce::<F>(s0_t)
+}
+
+/// Montgomery reduction of the 512 bit product of the scalar lane
+#[inline(always)]
+fn scalar_reduce<F: FieldConfig>(s0_t: [u64; 8]) -> [u64; 4] {
     let mut s0_r1 = [0_u64; 5];
-    (s0_r1[0], s0_r1[1]) = carrying_mul_add(s0_t[0], U64_I3[0], s0_r1[0], 0);
-    (s0_r1[1], s0_r1[2]) = carrying_mul_add(s0_t[0], U64_I3[1], s0_r1[1], 0);
-    (s0_r1[2], s0_r1[3]) = carrying_mul_add(s0_t[0], U64_I3[2], s0_r1[2], 0);
-    (s0_r1[3], s0_r1[4]) = carrying_mul_add(s0_t[0], U64_I3[3], s0_r1[3], 0);
+    (s0_r1[0], s0_r1[1]) = carrying_mul_add(s0_t[0], F::U64_I3[0], s0_r1[0], 0);
+    (s0_r1[1], s0_r1[2]) = carrying_mul_add(s0_t[0], F::U64_I3[1], s0_r1[1], 0);
+    (s0_r1[2], s0_r1[3]) = carrying_mul_add(s0_t[0], F::U64_I3[2], s0_r1[2], 0);
+    (s0_r1[3], s0_r1[4]) = carrying_mul_add(s0_t[0], F::U64_I3[3], s0_r1[3], 0);
 
     let mut s0_r2 = [0_u64; 5];
-    (s0_r2[0], s0_r2[1]) = carrying_mul_add(s0_t[1], U64_I2[0], s0_r2[0], 0);
-    (s0_r2[1], s0_r2[2]) = carrying_mul_add(s0_t[1], U64_I2[1], s0_r2[1], 0);
-    (s0_r2[2], s0_r2[3]) = carrying_mul_add(s0_t[1], U64_I2[2], s0_r2[2], 0);
-    (s0_r2[3], s0_r2[4]) = carrying_mul_add(s0_t[1], U64_I2[3], s0_r2[3], 0);
+    (s0_r2[0], s0_r2[1]) = carrying_mul_add(s0_t[1], F::U64_I2[0], s0_r2[0], 0);
+    (s0_r2[1], s0_r2[2]) = carrying_mul_add(s0_t[1], F::U64_I2[1], s0_r2[1], 0);
+    (s0_r2[2], s0_r2[3]) = carrying_mul_add(s0_t[1], F::U64_I2[2], s0_r2[2], 0);
+    (s0_r2[3], s0_r2[4]) = carrying_mul_add(s0_t[1], F::U64_I2[3], s0_r2[3], 0);
 
     let mut s0_r3 = [0_u64; 5];
-    (s0_r3[0], s0_r3[1]) = carrying_mul_add(s0_t[2], U64_I1[0], s0_r3[0], 0);
-    (s0_r3[1], s0_r3[2]) = carrying_mul_add(s0_t[2], U64_I1[1], s0_r3[1], 0);
-    (s0_r3[2], s0_r3[3]) = carrying_mul_add(s0_t[2], U64_I1[2], s0_r3[2], 0);
-    (s0_r3[3], s0_r3[4]) = carrying_mul_add(s0_t[2], U64_I1[3], s0_r3[3], 0);
+    (s0_r3[0], s0_r3[1]) = carrying_mul_add(s0_t[2], F::U64_I1[0], s0_r3[0], 0);
+    (s0_r3[1], s0_r3[2]) = carrying_mul_add(s0_t[2], F::U64_I1[1], s0_r3[1], 0);
+    (s0_r3[2], s0_r3[3]) = carrying_mul_add(s0_t[2], F::U64_I1[2], s0_r3[2], 0);
+    (s0_r3[3], s0_r3[4]) = carrying_mul_add(s0_t[2], F::U64_I1[3], s0_r3[3], 0);
 
     let s0_s = addv(addv(subarray!(s0_t, 3, 5), s0_r1), addv(s0_r2, s0_r3));
 
-    let s0_m = U64_MU0.wrapping_mul(s0_s[0]);
+    let s0_m = F::NP0.wrapping_mul(s0_s[0]);
     let mut s0_mp = [0_u64; 5];
-    (s0_mp[0], s0_mp[1]) = carrying_mul_add(s0_m, P[0], s0_mp[0], 0);
-    (s0_mp[1], s0_mp[2]) = carrying_mul_add(s0_m, P[1], s0_mp[1], 0);
-    (s0_mp[2], s0_mp[3]) = carrying_mul_add(s0_m, P[2], s0_mp[2], 0);
-    (s0_mp[3], s0_mp[4]) = carrying_mul_add(s0_m, P[3], s0_mp[3], 0);
+    (s0_mp[0], s0_mp[1]) = carrying_mul_add(s0_m, F::P[0], s0_mp[0], 0);
+    (s0_mp[1], s0_mp[2]) = carrying_mul_add(s0_m, F::P[1], s0_mp[1], 0);
+    (s0_mp[2], s0_mp[3]) = carrying_mul_add(s0_m, F::P[2], s0_mp[2], 0);
+    (s0_mp[3], s0_mp[4]) = carrying_mul_add(s0_m, F::P[3], s0_mp[3], 0);
 
-    let s0 = subarray!(addv(s0_s, s0_mp), 1, 4);
-    // ---------------------------------------------------------------------------------------------
-    (s0, v[0], v[1])
+    subarray!(addv(s0_s, s0_mp), 1, 4)
 }
 // -------------------------------------------------------------------------------------------------
 
@@ -303,29 +590,28 @@ pub const fn make_initial(low_count: usize, high_count: usize) -> u64 {
 }
 
 #[inline(always)]
-pub fn transpose_u256_to_simd(limbs: [[u64; 4]; 2]) -> [Simd<u64, 2>; 4] {
-    // This does not issue multiple ldp and zip which might be marginally faster.
-    [
-        Simd::from_array([limbs[0][0], limbs[1][0]]),
-        Simd::from_array([limbs[0][1], limbs[1][1]]),
-        Simd::from_array([limbs[0][2], limbs[1][2]]),
-        Simd::from_array([limbs[0][3], limbs[1][3]]),
-    ]
+pub fn transpose_u256_to_simd<const L: usize>(limbs: [[u64; 4]; L]) -> [Simd<u64, L>; 4] {
+    std::array::from_fn(|i| Simd::from_array(std::array::from_fn(|lane| limbs[lane][i])))
 }
 
 #[inline(always)]
-pub fn transpose_simd_to_u256(limbs: [Simd<u64, 2>; 4]) -> [[u64; 4]; 2] {
-    let mut result = [[0; 4]; 2];
+pub fn transpose_simd_to_u256<const L: usize>(limbs: [Simd<u64, L>; 4]) -> [[u64; 4]; L] {
+    let mut result = [[0; 4]; L];
     for i in 0..limbs.len() {
         let tmp = limbs[i].to_array();
-        result[0][i] = tmp[0];
-        result[1][i] = tmp[1];
+        for lane in 0..L {
+            result[lane][i] = tmp[lane];
+        }
     }
     result
 }
 
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
 #[inline(always)]
-fn u256_to_u260_shl2_simd(limbs: [Simd<u64, 2>; 4]) -> [Simd<u64, 2>; 5] {
+fn u256_to_u260_shl2_simd<const L: usize>(limbs: [Simd<u64, L>; 4]) -> [Simd<u64, L>; 5] {
     let [l0, l1, l2, l3] = limbs;
     [
         (l0 << 2) & Simd::splat(MASK52),
@@ -336,8 +622,12 @@ fn u256_to_u260_shl2_simd(limbs: [Simd<u64, 2>; 4]) -> [Simd<u64, 2>; 5] {
     ]
 }
 
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
 #[inline(always)]
-fn u260_to_u256_simd(limbs: [Simd<u64, 2>; 5]) -> [Simd<u64, 2>; 4] {
+fn u260_to_u256_simd<const L: usize>(limbs: [Simd<u64, L>; 5]) -> [Simd<u64, L>; 4] {
     let [l0, l1, l2, l3, l4] = limbs;
     [
         l0 | (l1 << 52),
@@ -347,10 +637,14 @@ fn u260_to_u256_simd(limbs: [Simd<u64, 2>; 5]) -> [Simd<u64, 2>; 4] {
     ]
 }
 
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
 #[inline(always)]
-fn smult_noinit_simd(s: Simd<u64, 2>, v: [u64; 5]) -> [Simd<u64, 2>; 6] {
+fn smult_noinit_simd<const L: usize>(s: Simd<u64, L>, v: [u64; 5]) -> [Simd<u64, L>; 6] {
     let mut t = [Simd::splat(0); 6];
-    let s: Simd<f64, 2> = unsafe { vcvtq_f64_u64(s.into()).into() };
+    let s: Simd<f64, L> = u52_to_f64_simd(s);
 
     for i in 0..v.len() {
         let p_hi = s.mul_add(Simd::splat(v[i] as f64), Simd::splat(C1));
@@ -361,11 +655,34 @@ fn smult_noinit_simd(s: Simd<u64, 2>, v: [u64; 5]) -> [Simd<u64, 2>; 6] {
     t
 }
 
+/// Convert limbs of at most 52 bits to floating point, which is exact regardless of rounding mode
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+#[inline(always)]
+fn u52_to_f64_simd<const L: usize>(v: Simd<u64, L>) -> Simd<f64, L> {
+    // Lowers to ucvtf
+    v.cast()
+}
+
+/// Convert limbs of at most 52 bits to floating point, which is exact regardless of rounding mode
+///
+/// x86_64 lacks an unsigned 64 bit conversion before AVX-512. Instead the limb is placed in the
+/// mantissa of 2^52 after which subtracting 2^52 is exact.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn u52_to_f64_simd<const L: usize>(v: Simd<u64, L>) -> Simd<f64, L> {
+    const TWO_52: u64 = 0x4330000000000000;
+    Simd::from_bits(v | Simd::splat(TWO_52)) - Simd::splat(f64::from_bits(TWO_52))
+}
+
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
 #[inline(always)]
-fn addv_simd<const N: usize>(
-    mut va: [Simd<u64, 2>; N],
-    vb: [Simd<u64, 2>; N],
-) -> [Simd<u64, 2>; N] {
+fn addv_simd<const N: usize, const L: usize>(
+    mut va: [Simd<u64, L>; N],
+    vb: [Simd<u64, L>; N],
+) -> [Simd<u64, L>; N] {
     for i in 0..va.len() {
         va[i] += vb[i];
     }
@@ -373,7 +690,10 @@ fn addv_simd<const N: usize>(
 }
 
 #[inline(always)]
-pub fn resolve_simd_add_truncate(s: [Simd<u64, 2>; 6], mp: [Simd<u64, 2>; 6]) -> [Simd<u64, 2>; 5] {
+pub fn resolve_simd_add_truncate<const L: usize>(
+    s: [Simd<u64, L>; 6],
+    mp: [Simd<u64, L>; 6],
+) -> [Simd<u64, L>; 5] {
     let mut out = [Simd::splat(0); 5];
     let mut carry = (s[0] + mp[0]) >> 52;
     for i in 0..5 {
@@ -384,51 +704,6 @@ pub fn resolve_simd_add_truncate(s: [Simd<u64, 2>; 6], mp: [Simd<u64, 2>; 6]) ->
     out
 }
 
-#[cfg(target_arch = "aarch64")]
-#[inline(never)]
-/// Set the floating point control register (FPCR) to a specified value
-///
-/// This function allows direct control of the ARM64 FPCR register, which controls
-/// floating point behavior including rounding modes, exception handling, and other
-/// floating point settings.
-///
-/// inline(never) to prevent the compiler from reordering this operation
-pub fn set_fpcr(fpcr: u64) {
-    // Defense-in-depth but can't be relied on
-    // From the documentation:
-    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
-    std::hint::black_box(fpcr);
-    unsafe {
-        core::arch::asm!(
-        "msr fpcr, {fpcr}",
-        fpcr = in(reg) fpcr
-        )
-    }
-}
-
-#[cfg(target_arch = "aarch64")]
-#[inline(never)]
-/// Set the floating point rounding mode to round to zero
-///
-/// inline(never) to prevent to compiler from reordering
-pub fn set_round_to_zero() -> u64 {
-    let fpcr: u64;
-    unsafe {
-        // Set RMode (bits 22-23) to 0b11 for round toward zero
-        core::arch::asm!(
-        "mrs {fpcr}, fpcr",             // Read current FPCR
-        "orr {tmp}, {fpcr}, #0b11<<22", // Set RMode bits to 11 using bit shift notation
-        "msr fpcr, {tmp}",             // Write back to FPCR
-        tmp = out(reg) _,
-        fpcr = out(reg) fpcr,
-        );
-    }
-
-    // Defense-in-depth but can't be relied on
-    // From the documentation:
-    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
-    std::hint::black_box(fpcr)
-}
 // -------------------------------------------------------------------------------------------------
 
 #[inline(always)]
@@ -437,9 +712,37 @@ pub fn carrying_mul_add(a: u64, b: u64, add: u64, carry: u64) -> (u64, u64) {
     (c as u64, (c >> 64) as u64)
 }
 
+/// Reference arithmetic and random inputs for the tests of every module
+#[cfg(test)]
+mod test_utils {
+    use num_bigint::BigUint;
+    use primitive_types::U256;
+    use rand::{Rng, rngs};
+
+    /// a * b mod p
+    pub fn mod_mul(a: U256, b: U256, p: U256) -> U256 {
+        let mut c = [0u64; 4];
+        c.copy_from_slice(&(a.full_mul(b) % p).0[0..4]);
+        U256(c)
+    }
+
+    /// A random value below `bound`
+    pub fn random_below(rng: &mut rngs::StdRng, bound: [u64; 4]) -> [u64; 4] {
+        let mut bytes = [0u8; 32];
+        rng.fill(&mut bytes);
+        (U256::from_little_endian(&bytes) % U256(bound)).0
+    }
+
+    pub fn to_biguint(limbs: [u64; 4]) -> BigUint {
+        BigUint::from_slice(&limbs.map(|l| [l as u32, (l >> 32) as u32]).concat())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{block_multiplier, constants};
+    use crate::config::{Bn254Fq, Bn254Fr, FieldConfig};
+    use crate::test_utils::mod_mul;
+    use crate::{block_multiplier, block_squarer, scalar};
     use primitive_types::U256;
     use rand::{Rng, SeedableRng, rngs};
 
@@ -450,19 +753,32 @@ mod tests {
         0x9f37631a3d9cbfac,
     ];
 
-    fn mod_mul(a: U256, b: U256) -> U256 {
-        let p = U256(constants::P);
-        let mut c = [0u64; 4];
-        c.copy_from_slice(&(a.full_mul(b) % p).0[0..4]);
-        U256(c)
-    }
+    // 2^256 - 2P, same relation to the modulus as OUTPUT_MAX
+    const OUTPUT_MAX_FQ: [u64; 4] = [
+        0x87bee7d24f060572,
+        0xd0fd2add2f1c6ae5,
+        0x8f5f7492fcfd4f44,
+        0x9f37631a3d9cbfac,
+    ];
 
-    #[test]
-    fn test_block_multiplier() {
+    type BlockMultiplier = fn(
+        [u64; 4],
+        [u64; 4],
+        [u64; 4],
+        [u64; 4],
+        [u64; 4],
+        [u64; 4],
+    ) -> ([u64; 4], [u64; 4], [u64; 4]);
+
+    fn check_block_multiplier<F: FieldConfig>(
+        block_multiplier: BlockMultiplier,
+        output_max: [u64; 4],
+    ) {
         let mut rng = rngs::StdRng::seed_from_u64(0);
-        let p = U256(constants::P);
-        let r = U256(constants::R);
-        let r_inv = U256(constants::R_INV);
+        let p = U256(F::P);
+        let r = U256(F::R);
+        let r_inv = U256(F::R_INV);
+        let mod_mul = |a, b| mod_mul(a, b, p);
 
         let mut s0_a_bytes = [0u8; 32];
         let mut s0_b_bytes = [0u8; 32];
@@ -499,12 +815,50 @@ mod tests {
                 v1_a_mont.0,
                 v1_b_mont.0,
             );
-            assert!(U256(s0) < U256(OUTPUT_MAX));
-            assert!(U256(v0) < U256(OUTPUT_MAX));
-            assert!(U256(v1) < U256(OUTPUT_MAX));
+            assert!(U256(s0) < U256(output_max));
+            assert!(U256(v0) < U256(output_max));
+            assert!(U256(v1) < U256(output_max));
             assert_eq!(mod_mul(U256(s0), r_inv), mod_mul(s0_a, s0_b));
             assert_eq!(mod_mul(U256(v0), r_inv), mod_mul(v0_a, v0_b));
             assert_eq!(mod_mul(U256(v1), r_inv), mod_mul(v1_a, v1_b));
         }
     }
+
+    #[test]
+    fn test_block_multiplier() {
+        check_block_multiplier::<Bn254Fr>(block_multiplier::<Bn254Fr>, OUTPUT_MAX);
+    }
+
+    #[test]
+    fn test_block_multiplier_bn254_fq() {
+        check_block_multiplier::<Bn254Fq>(block_multiplier::<Bn254Fq>, OUTPUT_MAX_FQ);
+    }
+
+    #[test]
+    fn test_scalar_block_multiplier() {
+        check_block_multiplier::<Bn254Fr>(scalar::block_multiplier::<Bn254Fr>, OUTPUT_MAX);
+    }
+
+    fn check_block_squarer<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for _ in 0..100000 {
+            let s0_a = rng.random();
+            let v0_a = rng.random();
+            let v1_a = rng.random();
+            assert_eq!(
+                block_squarer::<F>(s0_a, v0_a, v1_a),
+                block_multiplier::<F>(s0_a, s0_a, v0_a, v0_a, v1_a, v1_a)
+            );
+        }
+    }
+
+    #[test]
+    fn test_block_squarer() {
+        check_block_squarer::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_block_squarer_bn254_fq() {
+        check_block_squarer::<Bn254Fq>();
+    }
 }
@@ -0,0 +1,131 @@
+//! Rayon thread pools whose workers stay in round toward zero mode.
+//!
+//! [`crate::batch::mul_batch`] switches the rounding mode on every call. The workers of a
+//! [`RoundToZeroPool`] switch once when they start, after which [`par_mul_batch`] runs the batch
+//! loop on them without touching the control register.
+
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use crate::batch::mul_batch_rtz_impl;
+#[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+use crate::batch::mul_batch_scalar;
+use crate::config::FieldConfig;
+use crate::rounding::set_round_to_zero;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+/// Elements per task, a multiple of three such that triples don't straddle tasks
+const CHUNK: usize = 3 * 256;
+
+/// Thread pool whose workers run in round toward zero mode for their whole lifetime.
+pub struct RoundToZeroPool {
+    pool: ThreadPool,
+}
+
+impl RoundToZeroPool {
+    /// Pool with the default configuration of [`ThreadPoolBuilder`]
+    pub fn new() -> Result<Self, ThreadPoolBuildError> {
+        Self::with_builder(ThreadPoolBuilder::new())
+    }
+
+    /// Pool from a configured builder, whose start handler is replaced by the one switching the
+    /// rounding mode.
+    pub fn with_builder(builder: ThreadPoolBuilder) -> Result<Self, ThreadPoolBuildError> {
+        let pool = builder
+            .start_handler(|_| {
+                set_round_to_zero();
+            })
+            .build()?;
+        Ok(Self { pool })
+    }
+
+    /// The underlying pool. Work spawned on it must leave the rounding mode as it found it.
+    pub fn pool(&self) -> &ThreadPool {
+        &self.pool
+    }
+}
+
+/// Element wise Montgomery multiplication `out[i] = a[i] * b[i]` on the workers of `pool`.
+///
+/// The slices are split into tasks of whole triples, so the results are the same as those of
+/// [`crate::batch::mul_batch`]. Panics if the slices don't have the same length.
+pub fn par_mul_batch<F: FieldConfig>(
+    pool: &RoundToZeroPool,
+    a: &[[u64; 4]],
+    b: &[[u64; 4]],
+    out: &mut [[u64; 4]],
+) {
+    assert_eq!(a.len(), b.len(), "input lengths differ");
+    assert_eq!(a.len(), out.len(), "output length differs");
+
+    pool.pool.install(|| {
+        a.par_chunks(CHUNK)
+            .zip(b.par_chunks(CHUNK))
+            .zip(out.par_chunks_mut(CHUNK))
+            .for_each(|((a, b), out)| mul_batch_rtz::<F>(a, b, out));
+    });
+}
+
+/// [`crate::batch::mul_batch`] without switching the rounding mode, which has to be round to zero
+/// already
+fn mul_batch_rtz<F: FieldConfig>(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { mul_batch_rtz_avx2::<F>(a, b, out) };
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    mul_batch_rtz_impl::<F>(a, b, out);
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    mul_batch_scalar::<F>(a, b, out);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn mul_batch_rtz_avx2<F: FieldConfig>(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    mul_batch_rtz_impl::<F>(a, b, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::mul_batch;
+    use crate::config::Bn254Fr;
+    use crate::rounding::{RoundingMode, rounding_mode};
+    use rand::{Rng, SeedableRng, rngs};
+
+    #[test]
+    fn test_par_mul_batch() {
+        let pool = RoundToZeroPool::with_builder(ThreadPoolBuilder::new().num_threads(4)).unwrap();
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for len in (0..20).chain([CHUNK - 1, CHUNK, 5 * CHUNK + 2]) {
+            let a: Vec<[u64; 4]> = (0..len).map(|_| rng.random()).collect();
+            let b: Vec<[u64; 4]> = (0..len).map(|_| rng.random()).collect();
+            let mut expected = vec![[0; 4]; len];
+            mul_batch::<Bn254Fr>(&a, &b, &mut expected);
+            let mut out = vec![[0; 4]; len];
+            par_mul_batch::<Bn254Fr>(&pool, &a, &b, &mut out);
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn test_workers_round_to_zero() {
+        let caller = rounding_mode();
+        let pool = RoundToZeroPool::with_builder(ThreadPoolBuilder::new().num_threads(4)).unwrap();
+        let modes = pool.pool().broadcast(|_| rounding_mode());
+        assert_eq!(modes, vec![RoundingMode::TowardZero; 4]);
+        // The calling thread is left alone
+        assert_eq!(rounding_mode(), caller);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_par_mul_batch_length_mismatch() {
+        let pool = RoundToZeroPool::new().unwrap();
+        par_mul_batch::<Bn254Fr>(&pool, &[[0; 4]; 3], &[[0; 4]; 3], &mut [[0; 4]; 2]);
+    }
+}
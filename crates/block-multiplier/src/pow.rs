@@ -0,0 +1,373 @@
+//! Exponentiation and square roots on top of [`crate::block_multiplier`].
+//!
+//! Bases are in Montgomery form below [`FieldConfig::OUTPUT_MAX`], like the inputs of
+//! [`crate::arith`], exponents are plain integers. Results are in Montgomery form below P.
+
+use crate::arith::reduce;
+use crate::config::FieldConfig;
+use crate::derivation::{shr, sub};
+use crate::{scalar_mul, scalar_sqr};
+
+/// Window size of the fixed and sliding window exponentiations
+const WINDOW: usize = 4;
+
+/// base^exp with a sliding window on the scalar lane of [`crate::block_multiplier`].
+///
+/// Runs of zeros in the exponent cost a squaring per bit and every window a multiplication with a
+/// precomputed odd power. The running time depends on the exponent, see [`pow_fixed`].
+pub fn pow<F: FieldConfig>(base: [u64; 4], exp: [u64; 4]) -> [u64; 4] {
+    let base = reduce::<F>(base);
+    let bit = |i: usize| (exp[i / 64] >> (i % 64)) & 1;
+
+    // base^1, base^3, ..., base^(2^WINDOW - 1)
+    let base_sqr = sqr::<F>(base);
+    let mut table = [base; 1 << (WINDOW - 1)];
+    for i in 1..table.len() {
+        table[i] = mul::<F>(table[i - 1], base_sqr);
+    }
+
+    let mut out = F::R;
+    let mut started = false;
+    let mut i = 256;
+    while i > 0 {
+        if bit(i - 1) == 0 {
+            if started {
+                out = sqr::<F>(out);
+            }
+            i -= 1;
+            continue;
+        }
+
+        // The window runs from bit i - 1 down to its lowest set bit
+        let mut low = i.saturating_sub(WINDOW);
+        while bit(low) == 0 {
+            low += 1;
+        }
+        let value = (low..i).rev().fold(0, |value, j| (value << 1) | bit(j)) as usize;
+        if started {
+            for _ in low..i {
+                out = sqr::<F>(out);
+            }
+            out = mul::<F>(out, table[value >> 1]);
+        } else {
+            out = table[value >> 1];
+            started = true;
+        }
+        i = low;
+    }
+    out
+}
+
+/// base^exp with a fixed window on the scalar lane of [`crate::block_multiplier`].
+///
+/// The sequence of operations and memory accesses doesn't depend on the exponent.
+pub fn pow_fixed<F: FieldConfig>(base: [u64; 4], exp: [u64; 4]) -> [u64; 4] {
+    let base = reduce::<F>(base);
+
+    // base^0, ..., base^(2^WINDOW - 1)
+    let mut table = [F::R; 1 << WINDOW];
+    table[1] = base;
+    for i in 2..table.len() {
+        table[i] = mul::<F>(table[i - 1], base);
+    }
+
+    let windows = 256 / WINDOW;
+    let mut out = select(&table, digit(exp, windows - 1));
+    for w in (0..windows - 1).rev() {
+        for _ in 0..WINDOW {
+            out = sqr::<F>(out);
+        }
+        out = mul::<F>(out, select(&table, digit(exp, w)));
+    }
+    out
+}
+
+/// Three independent [`pow_fixed`] in lockstep on the three lanes of [`crate::block_multiplier`].
+///
+/// All lanes share the fixed window schedule, so the exponents can differ. Lanes that aren't needed
+/// can repeat another base and exponent. The rounding mode is switched only once for the whole
+/// exponentiation, as in [`crate::batch::mul_batch`].
+pub fn pow_lockstep<F: FieldConfig>(bases: [[u64; 4]; 3], exps: [[u64; 4]; 3]) -> [[u64; 4]; 3] {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { pow_lockstep_avx2::<F>(bases, exps) };
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        let guard = crate::rounding::RoundToZeroGuard::new();
+        let out = pow_lockstep_impl::<F, true>(bases, exps);
+        drop(guard);
+        out
+    }
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    {
+        pow_lockstep_impl::<F, false>(bases, exps)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn pow_lockstep_avx2<F: FieldConfig>(
+    bases: [[u64; 4]; 3],
+    exps: [[u64; 4]; 3],
+) -> [[u64; 4]; 3] {
+    let guard = crate::rounding::RoundToZeroGuard::new();
+    let out = pow_lockstep_impl::<F, true>(bases, exps);
+    drop(guard);
+    out
+}
+
+type Triple = ([u64; 4], [u64; 4], [u64; 4]);
+
+/// [`pow_lockstep`] on the SIMD kernels when `RTZ`, which requires round to zero, and on
+/// [`crate::scalar`] otherwise
+#[inline(always)]
+fn pow_lockstep_impl<F: FieldConfig, const RTZ: bool>(
+    bases: [[u64; 4]; 3],
+    exps: [[u64; 4]; 3],
+) -> [[u64; 4]; 3] {
+    let bases = bases.map(reduce::<F>);
+
+    let mut tables = [[F::R; 1 << WINDOW]; 3];
+    for (table, base) in tables.iter_mut().zip(bases) {
+        table[1] = base;
+    }
+    for i in 2..1 << WINDOW {
+        let [t0, t1, t2] = &tables;
+        let [b0, b1, b2] = bases;
+        let (p0, p1, p2) = mul3::<F, RTZ>(t0[i - 1], b0, t1[i - 1], b1, t2[i - 1], b2);
+        for (table, p) in tables.iter_mut().zip([p0, p1, p2]) {
+            table[i] = reduce::<F>(p);
+        }
+    }
+
+    let entries = |w: usize| -> [[u64; 4]; 3] {
+        std::array::from_fn(|lane| select(&tables[lane], digit(exps[lane], w)))
+    };
+
+    let windows = 256 / WINDOW;
+    let mut out = entries(windows - 1);
+    for w in (0..windows - 1).rev() {
+        for _ in 0..WINDOW {
+            let (s0, v0, v1) = sqr3::<F, RTZ>(out[0], out[1], out[2]);
+            out = [s0, v0, v1].map(reduce::<F>);
+        }
+        let e = entries(w);
+        let (s0, v0, v1) = mul3::<F, RTZ>(out[0], e[0], out[1], e[1], out[2], e[2]);
+        out = [s0, v0, v1].map(reduce::<F>);
+    }
+    out
+}
+
+/// A square root with the Tonelli–Shanks algorithm, or `None` for quadratic non-residues.
+///
+/// Works for any [`FieldConfig::TWO_ADICITY`], which is 28 for BN254 Fr. Which of the two roots
+/// is returned is unspecified. The running time depends on the input.
+pub fn sqrt<F: FieldConfig>(a: [u64; 4]) -> Option<[u64; 4]> {
+    let a = reduce::<F>(a);
+    if a == [0; 4] {
+        return Some(a);
+    }
+
+    // (Q - 1) / 2 for P - 1 = Q * 2^S with Q odd
+    let q_minus_one_half = const { shr(sub(F::P, [1, 0, 0, 0]).0, F::TWO_ADICITY + 1) };
+
+    // Invariants: x^2 = a * b, b^(2^(v - 1)) = ±1 and z is a primitive 2^v-th root of unity
+    let w = pow::<F>(a, q_minus_one_half);
+    let mut x = mul::<F>(a, w);
+    let mut b = mul::<F>(x, w);
+    let mut z = F::ROOT_OF_UNITY;
+    let mut v = F::TWO_ADICITY;
+    while b != F::R {
+        // The order of b is 2^k
+        let mut k = 0;
+        let mut b_pow = b;
+        while b_pow != F::R {
+            b_pow = sqr::<F>(b_pow);
+            k += 1;
+            if k == v {
+                // a^((P - 1) / 2) = -1
+                return None;
+            }
+        }
+
+        let mut w = z;
+        for _ in 0..v - k - 1 {
+            w = sqr::<F>(w);
+        }
+        z = sqr::<F>(w);
+        b = mul::<F>(b, z);
+        x = mul::<F>(x, w);
+        v = k;
+    }
+    Some(x)
+}
+
+// The kernels are called directly rather than passed in, which would call them through a shim that
+// lacks the target features of the caller
+#[inline(always)]
+fn mul3<F: FieldConfig, const RTZ: bool>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> Triple {
+    #[cfg(any(
+        all(target_arch = "aarch64", target_feature = "neon"),
+        target_arch = "x86_64"
+    ))]
+    if RTZ {
+        return crate::block_multiplier_rtz::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b);
+    }
+    crate::scalar::block_multiplier::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b)
+}
+
+#[inline(always)]
+fn sqr3<F: FieldConfig, const RTZ: bool>(s0_a: [u64; 4], v0_a: [u64; 4], v1_a: [u64; 4]) -> Triple {
+    #[cfg(any(
+        all(target_arch = "aarch64", target_feature = "neon"),
+        target_arch = "x86_64"
+    ))]
+    if RTZ {
+        return crate::block_squarer_rtz::<F>(s0_a, v0_a, v1_a);
+    }
+    crate::scalar::block_squarer::<F>(s0_a, v0_a, v1_a)
+}
+
+/// Product below P of a, b < P
+#[inline(always)]
+fn mul<F: FieldConfig>(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    reduce::<F>(scalar_mul::<F>(a, b))
+}
+
+/// Square below P of a < P
+#[inline(always)]
+fn sqr<F: FieldConfig>(a: [u64; 4]) -> [u64; 4] {
+    reduce::<F>(scalar_sqr::<F>(a))
+}
+
+/// The w-th window of the exponent, starting from the least significant bits
+#[inline(always)]
+fn digit(exp: [u64; 4], w: usize) -> usize {
+    let bit = w * WINDOW;
+    ((exp[bit / 64] >> (bit % 64)) & ((1 << WINDOW) - 1)) as usize
+}
+
+/// table[index], reading every entry
+#[inline(always)]
+fn select(table: &[[u64; 4]; 1 << WINDOW], index: usize) -> [u64; 4] {
+    let mut out = [0; 4];
+    for (i, entry) in table.iter().enumerate() {
+        let mask = ((i == index) as u64).wrapping_neg();
+        for (out, limb) in out.iter_mut().zip(entry) {
+            *out |= limb & mask;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bn254Fq, Bn254Fr};
+    use crate::test_utils::to_biguint;
+    use num_bigint::BigUint;
+    use rand::{Rng, SeedableRng, rngs};
+
+    fn from_biguint(a: &BigUint) -> [u64; 4] {
+        let mut out = [0; 4];
+        for (out, digit) in out.iter_mut().zip(a.to_u64_digits()) {
+            *out = digit;
+        }
+        out
+    }
+
+    fn check_pow<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = to_biguint(F::P);
+        let r = to_biguint(F::R);
+        let r_inv = to_biguint(F::R_INV);
+        let p_minus = |k: u64| from_biguint(&(&p - k));
+
+        let mut exps = vec![
+            [0; 4],
+            [1, 0, 0, 0],
+            [2, 0, 0, 0],
+            p_minus(1),
+            p_minus(2),
+            [u64::MAX; 4],
+        ];
+        exps.extend((0..100).map(|_| rng.random::<[u64; 4]>()));
+
+        for exp in exps {
+            let bases: [[u64; 4]; 3] =
+                std::array::from_fn(|_| from_biguint(&(to_biguint(rng.random()) % &p)));
+            // Exponents that differ per lane
+            let lane_exps = [exp, rng.random(), [exp[0], 0, 0, 0]];
+
+            let expected: [[u64; 4]; 3] = std::array::from_fn(|lane| {
+                let base = to_biguint(bases[lane]) * &r_inv % &p;
+                from_biguint(&(base.modpow(&to_biguint(lane_exps[lane]), &p) * &r % &p))
+            });
+
+            assert_eq!(pow::<F>(bases[0], exp), expected[0]);
+            assert_eq!(pow_fixed::<F>(bases[0], exp), expected[0]);
+            assert_eq!(pow_lockstep::<F>(bases, lane_exps), expected);
+        }
+    }
+
+    #[test]
+    fn test_pow() {
+        check_pow::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_pow_bn254_fq() {
+        check_pow::<Bn254Fq>();
+    }
+
+    fn check_sqrt<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = to_biguint(F::P);
+        let legendre_exp = (&p - 1u32) / 2u32;
+
+        assert_eq!(sqrt::<F>([0; 4]), Some([0; 4]));
+        assert_eq!(sqrt::<F>(F::R).map(|x| mul::<F>(x, x)), Some(F::R));
+
+        let mut residues = 0;
+        for _ in 0..1000 {
+            let a = from_biguint(&(to_biguint(rng.random()) % &p));
+            // Montgomery form multiplies by R, so a^((P - 1) / 2) is compared in normal form
+            let a_normal = to_biguint(a) * to_biguint(F::R_INV) % &p;
+            let is_residue = a_normal.modpow(&legendre_exp, &p) == BigUint::from(1u32);
+
+            match sqrt::<F>(a) {
+                Some(x) => {
+                    assert!(is_residue);
+                    assert_eq!(mul::<F>(x, x), a);
+                    residues += 1;
+                }
+                None => assert!(!is_residue),
+            }
+
+            let square = mul::<F>(a, a);
+            let x = sqrt::<F>(square).unwrap();
+            assert_eq!(mul::<F>(x, x), square);
+        }
+        assert!(residues > 400 && residues < 600);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        check_sqrt::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_sqrt_bn254_fq() {
+        check_sqrt::<Bn254Fq>();
+    }
+}
@@ -0,0 +1,382 @@
+//! Multiplication modulo pseudo-Mersenne primes, laid out like [`crate::block_multiplier`].
+//!
+//! For a modulus with a multiple of the form 2^256 - C and C small, 2^256 ≡ C and the upper half
+//! of a product folds into the lower half with a multiplication by C. That replaces the Montgomery
+//! reduction and its constants, and works directly on canonical values instead of Montgomery
+//! form. Curve25519 uses 2^256 - 38 = 2P, secp256k1 is of the form itself.
+//!
+//! The vector lanes use the same floating point products as [`crate::block_multiplier`], but
+//! without the 2 bit shift of the inputs: 2^260 ≡ 16 C folds the columns above 260 bits.
+
+use crate::derivation::{add, eq, sub};
+use crate::{addv, carrying_mul_add};
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use crate::{
+    constants::{C1, C2, MASK48, MASK52},
+    make_initial, rounding,
+    rounding::RoundToZeroGuard,
+    transpose_simd_to_u256, transpose_u256_to_simd, u52_to_f64_simd, u260_to_u256_simd,
+};
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+use std::simd::{Simd, StdFloat, num::SimdFloat};
+
+/// A modulus P for which 2^256 - C is a multiple of P.
+pub trait PseudoMersenne {
+    /// Modulus
+    const P: [u64; 4];
+    /// 2^256 mod the multiple of P. Has to be below 2^48 such that 16 C fits in a vector limb.
+    const C: u64;
+}
+
+/// 2^255 - 19, the base field of Curve25519
+pub struct Curve25519;
+
+impl PseudoMersenne for Curve25519 {
+    const P: [u64; 4] = [
+        0xffffffffffffffed,
+        0xffffffffffffffff,
+        0xffffffffffffffff,
+        0x7fffffffffffffff,
+    ];
+    const C: u64 = 38;
+}
+
+/// 2^256 - 2^32 - 977, the base field of secp256k1
+pub struct Secp256k1;
+
+impl PseudoMersenne for Secp256k1 {
+    const P: [u64; 4] = [
+        0xfffffffefffffc2f,
+        0xffffffffffffffff,
+        0xffffffffffffffff,
+        0xffffffffffffffff,
+    ];
+    const C: u64 = 0x1000003d1;
+}
+
+/// The K for which 2^256 - C = K P, which is also the number of subtractions of P that bring a
+/// value below 2^256 into the canonical range.
+///
+/// Panics (at compile time when used in a constant) when there is no such K of at most 2 or C
+/// doesn't fit in the vector lanes.
+pub const fn multiple(p: [u64; 4], c: u64) -> usize {
+    assert!(c < 1 << 48, "C must be smaller than 2^48");
+    let m = sub([0; 4], [c, 0, 0, 0]).0;
+    let (two_p, overflow) = add(p, p);
+    if eq(m, p) {
+        1
+    } else if !overflow && eq(m, two_p) {
+        2
+    } else {
+        panic!("2^256 - C must be P or 2P")
+    }
+}
+
+/// Multiplication modulo P of three independent pairs: one on the scalar (64 bit limb) unit and
+/// two on the vector (52 bit limb, floating point) unit.
+///
+/// Inputs are any 256 bit values, outputs are fully reduced below P. As the outputs are canonical
+/// the lanes agree with each other, and targets without the required SIMD support compute all of
+/// them on the scalar unit. On x86_64 the vector unit requires FMA, which is detected at runtime.
+pub fn block_multiplier<F: PseudoMersenne>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { block_multiplier_avx2::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b) };
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        block_multiplier_impl::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b)
+    }
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    {
+        (
+            scalar_mul::<F>(s0_a, s0_b),
+            scalar_mul::<F>(v0_a, v0_b),
+            scalar_mul::<F>(v1_a, v1_b),
+        )
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn block_multiplier_avx2<F: PseudoMersenne>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    block_multiplier_impl::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b)
+}
+
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn block_multiplier_impl<F: PseudoMersenne>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    let guard = RoundToZeroGuard::new();
+    rounding::assert_round_to_zero();
+
+    // -- [VECTOR] ---------------------------------------------------------------------------------
+    // See crate::block_multiplier_rtz for the black_box
+    let [v0_a, v0_b, v1_a, v1_b] = std::hint::black_box([v0_a, v0_b, v1_a, v1_b]);
+    let v_a = transpose_u256_to_simd([v0_a, v1_a]);
+    let v_b = transpose_u256_to_simd([v0_b, v1_b]);
+    let v = std::hint::black_box(transpose_simd_to_u256(mul_simd::<F, 2>(v_a, v_b)));
+    // ---------------------------------------------------------------------------------------------
+    // -- [SCALAR] ---------------------------------------------------------------------------------
+    let s0 = scalar_mul::<F>(s0_a, s0_b);
+    // ---------------------------------------------------------------------------------------------
+    drop(guard);
+    (s0, v[0], v[1])
+}
+
+/// The vector lanes of [`block_multiplier`]
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn mul_simd<F: PseudoMersenne, const L: usize>(
+    a: [Simd<u64, L>; 4],
+    b: [Simd<u64, L>; 4],
+) -> [Simd<u64, L>; 4] {
+    let k = const { multiple(F::P, F::C) };
+
+    let a = u256_to_u260_simd(a).map(u52_to_f64_simd);
+    let b = u256_to_u260_simd(b).map(u52_to_f64_simd);
+
+    // The initial values cancel the exponent bits of the products in each column
+    let mut t = [
+        make_initial(1, 0),
+        make_initial(2, 1),
+        make_initial(3, 2),
+        make_initial(4, 3),
+        make_initial(5, 4),
+        make_initial(4, 5),
+        make_initial(3, 4),
+        make_initial(2, 3),
+        make_initial(1, 2),
+        make_initial(0, 1),
+    ]
+    .map(Simd::splat);
+    for i in 0..a.len() {
+        for j in 0..b.len() {
+            let p_hi = a[i].mul_add(b[j], Simd::splat(C1));
+            let p_lo = a[i].mul_add(b[j], Simd::splat(C2) - p_hi);
+            t[i + j + 1] += p_hi.to_bits();
+            t[i + j] += p_lo.to_bits();
+        }
+    }
+    for i in 0..t.len() - 1 {
+        t[i + 1] += t[i] >> 52;
+        t[i] &= Simd::splat(MASK52);
+    }
+
+    // 2^260 ≡ 16 C: fold the upper five limbs onto the lower five
+    let d = Simd::splat((16 * F::C) as f64);
+    let mut r = [
+        t[0] + Simd::splat(make_initial(1, 0)),
+        t[1] + Simd::splat(make_initial(1, 1)),
+        t[2] + Simd::splat(make_initial(1, 1)),
+        t[3] + Simd::splat(make_initial(1, 1)),
+        t[4] + Simd::splat(make_initial(1, 1)),
+        Simd::splat(make_initial(0, 1)),
+    ];
+    for i in 0..5 {
+        let h = u52_to_f64_simd(t[i + 5]);
+        let p_hi = h.mul_add(d, Simd::splat(C1));
+        let p_lo = h.mul_add(d, Simd::splat(C2) - p_hi);
+        r[i + 1] += p_hi.to_bits();
+        r[i] += p_lo.to_bits();
+    }
+    for i in 0..r.len() - 1 {
+        r[i + 1] += r[i] >> 52;
+        r[i] &= Simd::splat(MASK52);
+    }
+
+    // r < 2^260 + C 2^256, fold everything from bit 256 with 2^256 ≡ C. h < C + 16 < 2^52.
+    let h = u52_to_f64_simd((r[4] >> 48) | (r[5] << 4));
+    let mut r = [r[0], r[1], r[2], r[3], r[4] & Simd::splat(MASK48)];
+    let p_hi = h.mul_add(Simd::splat(F::C as f64), Simd::splat(C1));
+    let p_lo = h.mul_add(Simd::splat(F::C as f64), Simd::splat(C2) - p_hi);
+    r[1] += p_hi.to_bits() + Simd::splat(make_initial(0, 1));
+    r[0] += p_lo.to_bits() + Simd::splat(make_initial(1, 0));
+    resolve_u256_simd(&mut r);
+
+    // r < 2^256 + 2^97, once more and only when bit 256 is set, in which case the rest is small
+    let h = r[4] >> 48;
+    r[4] &= Simd::splat(MASK48);
+    r[0] += h * Simd::splat(F::C);
+    resolve_u256_simd(&mut r);
+
+    // r < 2^256 = K P + C
+    for _ in 0..k {
+        r = sub_p_simd::<F, L>(r);
+    }
+    u260_to_u256_simd(r)
+}
+
+/// Convert 4x64 bit limbs into 5x52 bit limbs
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn u256_to_u260_simd<const L: usize>(limbs: [Simd<u64, L>; 4]) -> [Simd<u64, L>; 5] {
+    let [l0, l1, l2, l3] = limbs;
+    [
+        l0 & Simd::splat(MASK52),
+        ((l0 >> 52) | (l1 << 12)) & Simd::splat(MASK52),
+        ((l1 >> 40) | (l2 << 24)) & Simd::splat(MASK52),
+        ((l2 >> 28) | (l3 << 36)) & Simd::splat(MASK52),
+        l3 >> 16,
+    ]
+}
+
+/// Propagate the carries of 52 bit limbs, the carry out of the top limb stays in it
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn resolve_u256_simd<const L: usize>(r: &mut [Simd<u64, L>; 5]) {
+    for i in 0..r.len() - 1 {
+        r[i + 1] += r[i] >> 52;
+        r[i] &= Simd::splat(MASK52);
+    }
+}
+
+/// r - P if that doesn't underflow, r otherwise
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    target_arch = "x86_64"
+))]
+#[inline(always)]
+fn sub_p_simd<F: PseudoMersenne, const L: usize>(r: [Simd<u64, L>; 5]) -> [Simd<u64, L>; 5] {
+    let p = crate::derivation::u256_to_u260(F::P);
+    let mut d = [Simd::splat(0); 5];
+    let mut borrow = Simd::splat(0);
+    for i in 0..r.len() {
+        let tmp = r[i] - Simd::splat(p[i]) - borrow;
+        d[i] = tmp & Simd::splat(MASK52);
+        borrow = tmp >> 63;
+    }
+    // All ones when the subtraction underflowed
+    let keep = Simd::splat(0) - borrow;
+    std::array::from_fn(|i| (r[i] & keep) | (d[i] & !keep))
+}
+
+/// The scalar lane of [`block_multiplier`]
+#[inline(always)]
+fn scalar_mul<F: PseudoMersenne>(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    let k = const { multiple(F::P, F::C) };
+
+    let mut t = [0_u64; 8];
+    for i in 0..a.len() {
+        let mut carry = 0;
+        for j in 0..b.len() {
+            (t[i + j], carry) = carrying_mul_add(a[i], b[j], t[i + j], carry);
+        }
+        t[i + b.len()] = carry;
+    }
+
+    // 2^256 ≡ C: fold the upper four limbs onto the lower four, r[4] <= C
+    let mut r = [0_u64; 5];
+    let mut carry = 0;
+    for i in 0..4 {
+        (r[i], carry) = carrying_mul_add(t[i + 4], F::C, t[i], carry);
+    }
+    r[4] = carry;
+
+    // Once more for r[4], which can carry out of 256 bits only when the lower limbs are small
+    let (lo, hi) = carrying_mul_add(r[4], F::C, r[0], 0);
+    let s = addv([lo, r[1], r[2], r[3], 0], [0, hi, 0, 0, 0]);
+    let mut s = addv([s[0], s[1], s[2], s[3]], [s[4] * F::C, 0, 0, 0]);
+
+    // s < 2^256 = K P + C
+    for _ in 0..k {
+        let (d, borrow) = sub(s, F::P);
+        s = if borrow { s } else { d };
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Curve25519, PseudoMersenne, Secp256k1, block_multiplier, multiple};
+    use crate::test_utils::to_biguint;
+    use rand::{Rng, SeedableRng, rngs};
+
+    fn check_block_multiplier<F: PseudoMersenne>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = to_biguint(F::P);
+        let p_minus_one = {
+            let mut l = F::P;
+            l[0] -= 1;
+            l
+        };
+        let edge = [[0; 4], [1, 0, 0, 0], p_minus_one, F::P, [u64::MAX; 4]];
+
+        let mut inputs: Vec<[u64; 4]> = (0..3000).map(|_| rng.random()).collect();
+        for a in edge {
+            for b in edge {
+                inputs.extend([a, b]);
+            }
+        }
+        // Pad to a multiple of three pairs
+        inputs.extend(&edge[..4]);
+
+        for ab in inputs.chunks_exact(6) {
+            let (s0, v0, v1) = block_multiplier::<F>(ab[0], ab[1], ab[2], ab[3], ab[4], ab[5]);
+            for (out, (a, b)) in
+                [s0, v0, v1]
+                    .into_iter()
+                    .zip([(ab[0], ab[1]), (ab[2], ab[3]), (ab[4], ab[5])])
+            {
+                let expected = to_biguint(a) * to_biguint(b) % &p;
+                assert_eq!(to_biguint(out), expected, "{a:x?} * {b:x?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_multiple() {
+        assert_eq!(multiple(Curve25519::P, Curve25519::C), 2);
+        assert_eq!(multiple(Secp256k1::P, Secp256k1::C), 1);
+    }
+
+    #[test]
+    fn test_block_multiplier_curve25519() {
+        check_block_multiplier::<Curve25519>();
+    }
+
+    #[test]
+    fn test_block_multiplier_secp256k1() {
+        check_block_multiplier::<Secp256k1>();
+    }
+}
@@ -0,0 +1,269 @@
+//! Switching the floating point rounding mode to round toward zero.
+//!
+//! The floating point multiplication trick of the vector lanes requires round toward zero, which
+//! is controlled per thread by FPCR on aarch64 and by MXCSR on x86_64. [`RoundToZeroGuard`] switches
+//! to it and restores the previous mode when it goes out of scope. The `_rtz` kernels assert the
+//! rounding mode on entry in debug builds, and in release builds with the `assert-rounding`
+//! feature.
+
+use std::marker::PhantomData;
+
+/// Value of the floating point control register: FPCR on aarch64, MXCSR on x86_64
+#[cfg(target_arch = "aarch64")]
+pub type ControlRegister = u64;
+/// Value of the floating point control register: FPCR on aarch64, MXCSR on x86_64
+#[cfg(target_arch = "x86_64")]
+pub type ControlRegister = u32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    Nearest,
+    Up,
+    Down,
+    TowardZero,
+}
+
+/// Round toward zero until dropped, after which the previous control register value is restored.
+///
+/// Guards can be nested and restore in reverse order of creation, as scopes do. The guard is
+/// neither `Send` nor `Sync` as it restores the register of the thread that created it:
+///
+/// ```compile_fail
+/// use block_multiplier::rounding::RoundToZeroGuard;
+///
+/// fn assert_send<T: Send>(_: T) {}
+/// assert_send(RoundToZeroGuard::new());
+/// ```
+pub struct RoundToZeroGuard {
+    saved: ControlRegister,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl RoundToZeroGuard {
+    /// Switch the current thread to round toward zero
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self {
+            saved: set_round_to_zero(),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// The control register value that is restored on drop
+    pub fn saved(&self) -> ControlRegister {
+        self.saved
+    }
+}
+
+impl Default for RoundToZeroGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for RoundToZeroGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        #[cfg(target_arch = "aarch64")]
+        set_fpcr(self.saved);
+        #[cfg(target_arch = "x86_64")]
+        set_mxcsr(self.saved);
+    }
+}
+
+/// The rounding mode of the current thread
+pub fn rounding_mode() -> RoundingMode {
+    // The encodings of up and down differ between the architectures
+    #[cfg(target_arch = "aarch64")]
+    let mode = match (get_fpcr() >> 22) & 0b11 {
+        0b00 => RoundingMode::Nearest,
+        0b01 => RoundingMode::Up,
+        0b10 => RoundingMode::Down,
+        _ => RoundingMode::TowardZero,
+    };
+    #[cfg(target_arch = "x86_64")]
+    let mode = match (get_mxcsr() >> 13) & 0b11 {
+        0b00 => RoundingMode::Nearest,
+        0b01 => RoundingMode::Down,
+        0b10 => RoundingMode::Up,
+        _ => RoundingMode::TowardZero,
+    };
+    mode
+}
+
+/// Panic unless the current thread rounds toward zero, in debug builds or with the
+/// `assert-rounding` feature
+#[inline(always)]
+pub fn assert_round_to_zero() {
+    if cfg!(any(debug_assertions, feature = "assert-rounding")) {
+        assert_eq!(
+            rounding_mode(),
+            RoundingMode::TowardZero,
+            "kernel requires round toward zero, see RoundToZeroGuard"
+        );
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(never)]
+/// Read the floating point control register (FPCR)
+pub fn get_fpcr() -> u64 {
+    let fpcr: u64;
+    unsafe {
+        core::arch::asm!(
+        "mrs {fpcr}, fpcr",
+        fpcr = out(reg) fpcr,
+        )
+    }
+    fpcr
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(never)]
+/// Read the SSE control and status register (MXCSR)
+pub fn get_mxcsr() -> u32 {
+    let mut mxcsr: u32 = 0;
+    unsafe {
+        core::arch::asm!(
+        "stmxcsr [{mxcsr}]",
+        mxcsr = in(reg) &mut mxcsr,
+        options(nostack, preserves_flags)
+        )
+    }
+    mxcsr
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(never)]
+/// Set the floating point control register (FPCR) to a specified value
+///
+/// This function allows direct control of the ARM64 FPCR register, which controls
+/// floating point behavior including rounding modes, exception handling, and other
+/// floating point settings.
+///
+/// inline(never) to prevent the compiler from reordering this operation
+pub fn set_fpcr(fpcr: u64) {
+    // Defense-in-depth but can't be relied on
+    // From the documentation:
+    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
+    std::hint::black_box(fpcr);
+    unsafe {
+        core::arch::asm!(
+        "msr fpcr, {fpcr}",
+        fpcr = in(reg) fpcr
+        )
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(never)]
+/// Set the floating point rounding mode to round to zero
+///
+/// inline(never) to prevent to compiler from reordering
+pub fn set_round_to_zero() -> u64 {
+    let fpcr: u64;
+    unsafe {
+        // Set RMode (bits 22-23) to 0b11 for round toward zero
+        core::arch::asm!(
+        "mrs {fpcr}, fpcr",             // Read current FPCR
+        "orr {tmp}, {fpcr}, #0b11<<22", // Set RMode bits to 11 using bit shift notation
+        "msr fpcr, {tmp}",             // Write back to FPCR
+        tmp = out(reg) _,
+        fpcr = out(reg) fpcr,
+        );
+    }
+
+    // Defense-in-depth but can't be relied on
+    // From the documentation:
+    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
+    std::hint::black_box(fpcr)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(never)]
+/// Set the SSE control and status register (MXCSR) to a specified value
+///
+/// MXCSR controls the rounding mode of all SSE and AVX floating point operations.
+///
+/// inline(never) to prevent the compiler from reordering this operation
+pub fn set_mxcsr(mxcsr: u32) {
+    // Defense-in-depth but can't be relied on
+    // From the documentation:
+    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
+    std::hint::black_box(mxcsr);
+    unsafe {
+        core::arch::asm!(
+        "ldmxcsr [{mxcsr}]",
+        mxcsr = in(reg) &mxcsr,
+        options(nostack, preserves_flags)
+        )
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(never)]
+/// Set the floating point rounding mode to round to zero
+///
+/// inline(never) to prevent to compiler from reordering
+pub fn set_round_to_zero() -> u32 {
+    let mut mxcsr: u32 = 0;
+    unsafe {
+        // Set RC (bits 13-14) to 0b11 for round toward zero
+        core::arch::asm!(
+        "stmxcsr [{mxcsr}]",            // Read current MXCSR
+        mxcsr = in(reg) &mut mxcsr,
+        options(nostack, preserves_flags)
+        );
+        let tmp = mxcsr | (0b11 << 13);
+        core::arch::asm!(
+        "ldmxcsr [{tmp}]",              // Write back to MXCSR
+        tmp = in(reg) &tmp,
+        options(nostack, preserves_flags)
+        );
+    }
+
+    // Defense-in-depth but can't be relied on
+    // From the documentation:
+    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
+    std::hint::black_box(mxcsr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_restores() {
+        let before = rounding_mode();
+        {
+            let _guard = RoundToZeroGuard::new();
+            assert_eq!(rounding_mode(), RoundingMode::TowardZero);
+        }
+        assert_eq!(rounding_mode(), before);
+    }
+
+    #[test]
+    fn test_nested_guards() {
+        let before = rounding_mode();
+        {
+            let outer = RoundToZeroGuard::new();
+            {
+                let inner = RoundToZeroGuard::new();
+                // The outer guard saved the original mode, the inner one round toward zero
+                assert_ne!(inner.saved(), outer.saved());
+                assert_eq!(rounding_mode(), RoundingMode::TowardZero);
+            }
+            // The inner guard restored the mode that the outer guard set
+            assert_eq!(rounding_mode(), RoundingMode::TowardZero);
+        }
+        assert_eq!(rounding_mode(), before);
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "assert-rounding"))]
+    #[should_panic(expected = "round toward zero")]
+    fn test_assert_round_to_zero() {
+        assert_ne!(rounding_mode(), RoundingMode::TowardZero);
+        assert_round_to_zero();
+    }
+}
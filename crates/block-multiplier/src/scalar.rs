@@ -0,0 +1,179 @@
+//! Pure integer implementation of [`crate::block_multiplier`].
+//!
+//! The vector lanes are emulated with 64 bit integer arithmetic on the same 52 bit limbs,
+//! splitting each 104 bit product exactly where the floating point trick does. The results are
+//! therefore bit for bit the same as those of the SIMD implementation, including the output bound.
+//! [`crate::block_multiplier`] selects this implementation on targets without the required SIMD
+//! support.
+
+use crate::config::FieldConfig;
+use crate::constants::MASK52;
+use crate::{derivation, scalar_mul, scalar_sqr};
+
+/// Same as [`crate::block_multiplier`] but without SIMD or floating point.
+pub fn block_multiplier<F: FieldConfig>(
+    s0_a: [u64; 4],
+    s0_b: [u64; 4],
+    v0_a: [u64; 4],
+    v0_b: [u64; 4],
+    v1_a: [u64; 4],
+    v1_b: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    const { derivation::assert_modulus(F::P) };
+
+    let s0 = scalar_mul::<F>(s0_a, s0_b);
+    let v0 = vector_lane_mul::<F>(v0_a, v0_b);
+    let v1 = vector_lane_mul::<F>(v1_a, v1_b);
+    (s0, v0, v1)
+}
+
+/// Same as [`crate::block_squarer`] but without SIMD or floating point.
+pub fn block_squarer<F: FieldConfig>(
+    s0_a: [u64; 4],
+    v0_a: [u64; 4],
+    v1_a: [u64; 4],
+) -> ([u64; 4], [u64; 4], [u64; 4]) {
+    const { derivation::assert_modulus(F::P) };
+
+    // Squaring through the general lanes gives the same partial product sums
+    let s0 = scalar_sqr::<F>(s0_a);
+    let v0 = vector_lane_mul::<F>(v0_a, v0_a);
+    let v1 = vector_lane_mul::<F>(v1_a, v1_a);
+    (s0, v0, v1)
+}
+
+/// One lane of the vector unit of [`crate::block_multiplier`]
+#[inline(always)]
+pub(crate) fn vector_lane_mul<F: FieldConfig>(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    let a = u256_to_u260_shl2(a);
+    let b = u256_to_u260_shl2(b);
+
+    let mut t = [0_u64; 10];
+    for i in 0..a.len() {
+        for j in 0..b.len() {
+            let (lo, hi) = mul_u52(a[i], b[j]);
+            t[i + j + 1] = t[i + j + 1].wrapping_add(hi);
+            t[i + j] = t[i + j].wrapping_add(lo);
+        }
+    }
+
+    t[1] += t[0] >> 52;
+    t[2] += t[1] >> 52;
+    t[3] += t[2] >> 52;
+    t[4] += t[3] >> 52;
+
+    let r0 = smult_noinit(t[0] & MASK52, F::RHO_4);
+    let r1 = smult_noinit(t[1] & MASK52, F::RHO_3);
+    let r2 = smult_noinit(t[2] & MASK52, F::RHO_2);
+    let r3 = smult_noinit(t[3] & MASK52, F::RHO_1);
+
+    let s = [t[4], t[5], t[6], t[7], t[8], t[9]];
+
+    let s = addv_lane(r3, addv_lane(addv_lane(s, r0), addv_lane(r1, r2)));
+
+    let m = s[0].wrapping_mul(F::U52_NP0) & MASK52;
+    let mp = smult_noinit(m, F::U52_P);
+
+    u260_to_u256(resolve_add_truncate(s, mp))
+}
+
+/// Low and high 52 bits of the product of two values below 2^52
+#[inline(always)]
+fn mul_u52(a: u64, b: u64) -> (u64, u64) {
+    let p = a as u128 * b as u128;
+    (p as u64 & MASK52, (p >> 52) as u64)
+}
+
+#[inline(always)]
+fn smult_noinit(s: u64, v: [u64; 5]) -> [u64; 6] {
+    let mut t = [0; 6];
+    for i in 0..v.len() {
+        let (lo, hi) = mul_u52(s, v[i]);
+        t[i + 1] += hi;
+        t[i] += lo;
+    }
+    t
+}
+
+/// Limb wise addition without carry propagation, like addition of SIMD registers
+#[inline(always)]
+fn addv_lane<const N: usize>(mut a: [u64; N], b: [u64; N]) -> [u64; N] {
+    for i in 0..a.len() {
+        a[i] = a[i].wrapping_add(b[i]);
+    }
+    a
+}
+
+#[inline(always)]
+fn resolve_add_truncate(s: [u64; 6], mp: [u64; 6]) -> [u64; 5] {
+    let mut out = [0; 5];
+    let mut carry = s[0].wrapping_add(mp[0]) >> 52;
+    for i in 0..5 {
+        let tmp = s[i + 1].wrapping_add(mp[i + 1]).wrapping_add(carry);
+        out[i] = tmp & MASK52;
+        carry = tmp >> 52;
+    }
+    out
+}
+
+/// 4a in 5x52 bit limbs, as the vector lanes take their inputs
+#[inline(always)]
+pub fn u256_to_u260_shl2(limbs: [u64; 4]) -> [u64; 5] {
+    let [l0, l1, l2, l3] = limbs;
+    [
+        (l0 << 2) & MASK52,
+        ((l0 >> 50) | (l1 << 14)) & MASK52,
+        ((l1 >> 38) | (l2 << 26)) & MASK52,
+        ((l2 >> 26) | (l3 << 38)) & MASK52,
+        l3 >> 14,
+    ]
+}
+
+/// The lower 256 bits of 5x52 bit limbs, the inverse of [`crate::derivation::u256_to_u260`]
+#[inline(always)]
+pub fn u260_to_u256(limbs: [u64; 5]) -> [u64; 4] {
+    let [l0, l1, l2, l3, l4] = limbs;
+    [
+        l0 | (l1 << 52),
+        (l1 >> 12) | (l2 << 40),
+        (l2 >> 24) | (l3 << 28),
+        (l3 >> 36) | (l4 << 16),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Bn254Fq, Bn254Fr};
+    use rand::{Rng, SeedableRng, rngs};
+
+    // The results are the same for any input, not only for those that satisfy the input bounds
+    fn check_matches_simd<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        for _ in 0..10000 {
+            let mut inputs = [[0u64; 4]; 6];
+            for input in inputs.iter_mut() {
+                rng.fill(input);
+            }
+            let [s0_a, s0_b, v0_a, v0_b, v1_a, v1_b] = inputs;
+            assert_eq!(
+                block_multiplier::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b),
+                crate::block_multiplier::<F>(s0_a, s0_b, v0_a, v0_b, v1_a, v1_b)
+            );
+            assert_eq!(
+                block_squarer::<F>(s0_a, v0_a, v1_a),
+                crate::block_squarer::<F>(s0_a, v0_a, v1_a)
+            );
+        }
+    }
+
+    #[test]
+    fn test_scalar_matches_simd() {
+        check_matches_simd::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_scalar_matches_simd_bn254_fq() {
+        check_matches_simd::<Bn254Fq>();
+    }
+}
@@ -0,0 +1,244 @@
+//! The vector lanes of [`crate::block_multiplier`] on 52 bit limbs, for chains of multiplications.
+//!
+//! [`crate::block_multiplier`] converts its inputs from 4x64 to 5x52 bit limbs and the products
+//! back on every call. For long chains of multiplications, such as Poseidon rounds or polynomial
+//! evaluation, the elements can stay in 52 bit limbs instead, converting only at the ends with
+//! [`from_u256`] and [`to_u256`].
+//!
+//! # Representation
+//!
+//! Limbs `[l0, l1, l2, l3, l4]` hold `l0 + l1 2^52 + l2 2^104 + l3 2^156 + l4 2^208` per lane.
+//! Elements are in Montgomery form with the radix 2^260 of the limbs rather than the 2^256 of
+//! [`crate::block_multiplier`], and are not reduced modulo P. [`mul`] requires of its inputs
+//!
+//! - `l0..=l3 < 2^52`
+//! - `l4 < 2^50`, such that the value is below 2^258
+//!
+//! and its outputs satisfy the same bounds, so they can be used as inputs again. The product is
+//! below AB/2^260 + ρ1 + ρ2 + ρ3 + ρ4 + P with the reduction constants ρk = 2^-52k mod P, which
+//! for inputs below 2^258 and P < 2^254 is below 2^256 + 5P < 2^258.
+
+use crate::arith::reduce;
+use crate::config::FieldConfig;
+use crate::constants::MASK52;
+use crate::derivation::{self, add, inv_pow2_mod, shr, sub, u256_to_u260};
+use crate::rounding::{self, RoundToZeroGuard};
+use crate::{
+    mul_u260_simd, transpose_simd_to_u256, transpose_u256_to_simd, u256_to_u260_shl2_simd,
+    u260_to_u256_simd,
+};
+use std::simd::Simd;
+use std::simd::cmp::SimdPartialOrd;
+
+/// Limbs of the two lanes from elements in Montgomery form, lane `i` holding `values[i]`.
+///
+/// Any 256 bit values are accepted.
+pub fn from_u256<F: FieldConfig>(values: [[u64; 4]; 2]) -> [Simd<u64, 2>; 5] {
+    // 4a times 2^262 is 16a = a 2^4 in the 2^260 Montgomery form
+    let shl2 = u256_to_u260_shl2_simd(transpose_u256_to_simd(values));
+    mul::<F>(
+        shl2,
+        const { u256_to_u260(derivation::pow2_mod(262, F::P)) }.map(Simd::splat),
+    )
+}
+
+/// Elements in Montgomery form below P from the limbs of the two lanes.
+pub fn to_u256<F: FieldConfig>(limbs: [Simd<u64, 2>; 5]) -> [[u64; 4]; 2] {
+    const { assert_to_u256_bound(F::P, F::OUTPUT_MAX) };
+    // Multiplying with R divides the 2^260 Montgomery form by 2^4
+    let product = mul::<F>(limbs, const { u256_to_u260(F::R) }.map(Simd::splat));
+    transpose_simd_to_u256(u260_to_u256_simd(product)).map(reduce::<F>)
+}
+
+/// Montgomery multiplication per lane of limbs within the bounds of the module documentation.
+///
+/// Switches to round toward zero and back on every call, see [`mul_rtz`] for chains.
+pub fn mul<F: FieldConfig>(a: [Simd<u64, 2>; 5], b: [Simd<u64, 2>; 5]) -> [Simd<u64, 2>; 5] {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { mul_avx2::<F>(a, b) };
+    }
+
+    let guard = RoundToZeroGuard::new();
+    let out = mul_rtz_impl::<F>(a, b);
+    drop(guard);
+    out
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn mul_avx2<F: FieldConfig>(
+    a: [Simd<u64, 2>; 5],
+    b: [Simd<u64, 2>; 5],
+) -> [Simd<u64, 2>; 5] {
+    let guard = RoundToZeroGuard::new();
+    let out = mul_rtz_impl::<F>(a, b);
+    drop(guard);
+    out
+}
+
+/// [`mul`] without switching the rounding mode, for callers that hold a [`RoundToZeroGuard`]
+/// across a chain of multiplications.
+pub fn mul_rtz<F: FieldConfig>(a: [Simd<u64, 2>; 5], b: [Simd<u64, 2>; 5]) -> [Simd<u64, 2>; 5] {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required target features are available
+        return unsafe { mul_rtz_avx2::<F>(a, b) };
+    }
+
+    mul_rtz_impl::<F>(a, b)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn mul_rtz_avx2<F: FieldConfig>(
+    a: [Simd<u64, 2>; 5],
+    b: [Simd<u64, 2>; 5],
+) -> [Simd<u64, 2>; 5] {
+    mul_rtz_impl::<F>(a, b)
+}
+
+#[inline(always)]
+fn mul_rtz_impl<F: FieldConfig>(a: [Simd<u64, 2>; 5], b: [Simd<u64, 2>; 5]) -> [Simd<u64, 2>; 5] {
+    const { derivation::assert_modulus(F::P) };
+    rounding::assert_round_to_zero();
+    debug_assert_limbs(a);
+    debug_assert_limbs(b);
+
+    // See block_multiplier_rtz for the black_box
+    let [a, b] = std::hint::black_box([a, b]);
+    std::hint::black_box(mul_u260_simd::<F, 2>(a, b))
+}
+
+#[inline(always)]
+fn debug_assert_limbs(limbs: [Simd<u64, 2>; 5]) {
+    debug_assert!(limbs_in_bounds(limbs), "limbs out of bounds");
+}
+
+#[inline(always)]
+fn limbs_in_bounds(limbs: [Simd<u64, 2>; 5]) -> bool {
+    limbs[..4]
+        .iter()
+        .all(|l| l.simd_le(Simd::splat(MASK52)).all())
+        && limbs[4].simd_lt(Simd::splat(1 << 50)).all()
+}
+
+/// The product with R in [`to_u256`] is below (2^258 R)/2^260 + ρ1 + ρ2 + ρ3 + ρ4 + P, which has
+/// to be a valid input of [`reduce`].
+const fn assert_to_u256_bound(p: [u64; 4], output_max: [u64; 4]) {
+    let mut bound = add(shr(p, 2), p).0;
+    let mut k = 1;
+    while k <= 4 {
+        let (sum, carry) = add(bound, inv_pow2_mod(52 * k, p));
+        assert!(!carry, "modulus too large to convert from 52 bit limbs");
+        bound = sum;
+        k += 1;
+    }
+    assert!(
+        sub(bound, output_max).1,
+        "modulus too large to convert from 52 bit limbs"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_multiplier;
+    use crate::config::{Bn254Fq, Bn254Fr};
+    use crate::test_utils::to_biguint;
+    use num_bigint::BigUint;
+    use rand::{Rng, SeedableRng, rngs};
+
+    fn lane_to_biguint(limbs: [Simd<u64, 2>; 5], lane: usize) -> BigUint {
+        limbs
+            .iter()
+            .rev()
+            .fold(BigUint::ZERO, |acc, l| (acc << 52) + l[lane])
+    }
+
+    fn random_limbs(rng: &mut rngs::StdRng) -> [Simd<u64, 2>; 5] {
+        let mut limbs: [Simd<u64, 2>; 5] =
+            std::array::from_fn(|_| Simd::from_array(rng.random()) & Simd::splat(MASK52));
+        limbs[4] >>= 2;
+        limbs
+    }
+
+    fn check_round_trip<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = to_biguint(F::P);
+        for _ in 0..1000 {
+            let values: [[u64; 4]; 2] = rng.random();
+            let out = to_u256::<F>(from_u256::<F>(values));
+            for (value, out) in values.iter().zip(out) {
+                assert_eq!(to_biguint(out), to_biguint(*value) % &p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        check_round_trip::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_round_trip_bn254_fq() {
+        check_round_trip::<Bn254Fq>();
+    }
+
+    fn check_mul<F: FieldConfig>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = to_biguint(F::P);
+        let r_inv = BigUint::from(2u32).pow(260).modinv(&p).unwrap();
+
+        let max = [MASK52, MASK52, MASK52, MASK52, (1 << 50) - 1].map(Simd::splat);
+        let mut inputs = vec![(max, max), ([Simd::splat(0); 5], max)];
+        inputs.extend((0..1000).map(|_| (random_limbs(&mut rng), random_limbs(&mut rng))));
+        for (a, b) in inputs {
+            let out = mul::<F>(a, b);
+            assert!(limbs_in_bounds(out), "limbs out of bounds");
+            for lane in 0..2 {
+                let expected = lane_to_biguint(a, lane) * lane_to_biguint(b, lane) * &r_inv % &p;
+                assert_eq!(lane_to_biguint(out, lane) % &p, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mul() {
+        check_mul::<Bn254Fr>();
+    }
+
+    #[test]
+    fn test_mul_bn254_fq() {
+        check_mul::<Bn254Fq>();
+    }
+
+    #[test]
+    fn test_chain_matches_block_multiplier() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        // Below 2^253 < P
+        let mut random = || {
+            let mut a: [u64; 4] = rng.random();
+            a[3] >>= 3;
+            a
+        };
+        let values = [random(), random()];
+        let factors: Vec<[[u64; 4]; 2]> = (0..100).map(|_| [random(), random()]).collect();
+
+        let mut expected = values;
+        for [f0, f1] in &factors {
+            let (_, v0, v1) =
+                block_multiplier::<Bn254Fr>([0; 4], [0; 4], expected[0], *f0, expected[1], *f1);
+            expected = [v0, v1].map(reduce::<Bn254Fr>);
+        }
+
+        let guard = RoundToZeroGuard::new();
+        let mut acc = from_u256::<Bn254Fr>(values);
+        for factors in &factors {
+            acc = mul_rtz::<Bn254Fr>(acc, from_u256::<Bn254Fr>(*factors));
+        }
+        drop(guard);
+        assert_eq!(to_u256::<Bn254Fr>(acc), expected);
+    }
+}
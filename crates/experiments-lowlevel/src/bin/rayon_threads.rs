@@ -1,3 +1,5 @@
+use block_multiplier::pool::RoundToZeroPool;
+use block_multiplier::rounding::{RoundingMode, rounding_mode, set_round_to_zero};
 use num_cpus;
 use rayon::prelude::*;
 use std::thread;
@@ -28,70 +30,19 @@ fn get_cpu_core() -> Option<usize> {
     }
 }
 
-#[cfg(target_arch = "aarch64")]
-#[inline(never)]
-/// Set the floating point rounding mode to round to zero
-///
-/// inline(never) to prevent to compiler from reordering
-pub fn set_round_to_zero() -> u64 {
-    let fpcr: u64;
-    unsafe {
-        // Set RMode (bits 22-23) to 0b11 for round toward zero
-        core::arch::asm!(
-            "mrs {fpcr}, fpcr",             // Read current FPCR
-            "orr {tmp}, {fpcr}, #0b11<<22", // Set RMode bits to 11 using bit shift notation
-            "msr fpcr, {tmp}",             // Write back to FPCR
-            tmp = out(reg) _,
-            fpcr = out(reg) fpcr,
-        );
-    }
-
-    // Defense-in-depth but can't be relied on
-    // From the documentation:
-    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
-    std::hint::black_box(fpcr)
-}
-
-#[cfg(not(target_arch = "aarch64"))]
-pub fn set_round_to_zero() -> u64 {
-    // No-op or panic depending on your needs for non-ARM platforms
-    unimplemented!("Round to zero is only implemented for ARM64");
-}
-
 #[cfg(not(target_os = "linux"))]
 fn get_cpu_core() -> Option<usize> {
     // Return None for non-Linux platforms (including macOS)
     None
 }
 
-// Function to read the floating point rounding mode from FPCR
-#[cfg(target_arch = "aarch64")]
-fn get_fpcr_rounding_mode() -> u64 {
-    let fpcr: u64;
-    unsafe {
-        core::arch::asm!(
-            "mrs {tmp}, fpcr",
-            tmp = out(reg) fpcr
-        );
-    }
-    // Extract RMode bits (bits 22-23)
-    (fpcr >> 22) & 0b11
-}
-
-#[cfg(not(target_arch = "aarch64"))]
-fn get_fpcr_rounding_mode() -> u64 {
-    // Return a placeholder value for non-ARM platforms
-    todo!("Implement reading rounding mode for non-ARM platforms")
-}
-
 // Function to interpret the rounding mode value
-fn rounding_mode_to_string(mode: u64) -> &'static str {
+fn rounding_mode_to_string(mode: RoundingMode) -> &'static str {
     match mode {
-        0b00 => "Round to Nearest, ties to Even",
-        0b01 => "Round towards Plus Infinity",
-        0b10 => "Round towards Minus Infinity",
-        0b11 => "Round towards Zero",
-        _ => unreachable!("Invalid rounding mode"),
+        RoundingMode::Nearest => "Round to Nearest, ties to Even",
+        RoundingMode::Up => "Round towards Plus Infinity",
+        RoundingMode::Down => "Round towards Minus Infinity",
+        RoundingMode::TowardZero => "Round towards Zero",
     }
 }
 
@@ -141,9 +92,9 @@ fn main() {
     ensure_round_to_zero_on_core();
     
     // Print main thread's rounding mode
-    let main_thread_mode = get_fpcr_rounding_mode();
+    let main_thread_mode = rounding_mode();
     println!(
-        "Main thread set floating point rounding mode: {} ({})",
+        "Main thread set floating point rounding mode: {:?} ({})",
         main_thread_mode,
         rounding_mode_to_string(main_thread_mode)
     );
@@ -185,7 +136,7 @@ fn main() {
         let thread_id_numeric = get_thread_id_numeric();
         
         // Read the floating point rounding mode for this thread
-        let fp_mode = get_fpcr_rounding_mode();
+        let fp_mode = rounding_mode();
 
         // Simulate some work
         let result = perform_task(task_id);
@@ -195,14 +146,14 @@ fn main() {
         {
             if let Some(core_id) = get_cpu_core() {
                 println!(
-                    "Task {} processed by thread {:?} (#{}) on CPU core {}, using fp rounding mode: {} ({}), {}result: {}",
+                    "Task {} processed by thread {:?} (#{}) on CPU core {}, using fp rounding mode: {:?} ({}), {}result: {}",
                     task_id, thread_id, thread_id_numeric, core_id, fp_mode, rounding_mode_to_string(fp_mode), 
                     if newly_initialized { "NEWLY INITIALIZED, " } else { "" },
                     result
                 );
             } else {
                 println!(
-                    "Task {} processed by thread {:?} (#{}) (core unknown), using fp rounding mode: {} ({}), {}result: {}",
+                    "Task {} processed by thread {:?} (#{}) (core unknown), using fp rounding mode: {:?} ({}), {}result: {}",
                     task_id, thread_id, thread_id_numeric, fp_mode, rounding_mode_to_string(fp_mode),
                     if newly_initialized { "NEWLY INITIALIZED, " } else { "" },
                     result
@@ -213,13 +164,24 @@ fn main() {
         #[cfg(not(target_os = "linux"))]
         {
             println!(
-                "Task {} processed by thread {:?} (#{}) using fp rounding mode: {} ({}), {}result: {}",
+                "Task {} processed by thread {:?} (#{}) using fp rounding mode: {:?} ({}), {}result: {}",
                 task_id, thread_id, thread_id_numeric, fp_mode, rounding_mode_to_string(fp_mode),
                 if newly_initialized { "NEWLY INITIALIZED, " } else { "" },
                 result
             );
         }
     });
+
+    // The reusable version: the workers of a RoundToZeroPool switch once when they start
+    let pool = RoundToZeroPool::new().expect("failed to build the thread pool");
+    for (index, mode) in pool.pool().broadcast(|ctx| (ctx.index(), rounding_mode())) {
+        println!(
+            "RoundToZeroPool worker {} using fp rounding mode: {:?} ({})",
+            index,
+            mode,
+            rounding_mode_to_string(mode)
+        );
+    }
 }
 
 // Function to simulate work being done in each task
@@ -2,10 +2,12 @@
 use std::simd::Simd;
 
 use block_multiplier::constants::{NP0, P, U52_NP0, U52_P};
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
 use montgomery_reduction::arith::school_method;
 use montgomery_reduction::emmart;
-use montgomery_reduction::{acar, domb, interleaved, yuval};
+use montgomery_reduction::small_field::monty31::{BabyBear, KoalaBear, MontyField31};
+use montgomery_reduction::small_field::{goldilocks, mersenne31, monty31};
+use montgomery_reduction::{acar, barrett, domb, interleaved, yuval};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 
@@ -299,6 +301,97 @@ fn bench_emmart(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_barrett(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Barrett");
+
+    // Generate and print a random seed
+    let seed: u64 = rand::random();
+    println!("Using random seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    // SET ROUND TO ZERO BENCHES
+    emmart::set_round_to_zero();
+    // Below 2^253 < P, the canonical inputs of the non lazy variants
+    let mut a = rng.random::<[u64; 4]>();
+    let mut b = rng.random::<[u64; 4]>();
+    a[3] >>= 3;
+    b[3] >>= 3;
+
+    group.bench_function("cios_opt_random", |bencher| {
+        bencher.iter(|| acar::cios_opt(black_box(a), black_box(b), P, NP0))
+    });
+
+    group.bench_function("mul_random", |bencher| {
+        bencher.iter(|| barrett::mul(black_box(a), black_box(b), P, barrett::MU))
+    });
+
+    group.bench_function("mul_lazy_random", |bencher| {
+        bencher.iter(|| barrett::mul_lazy(black_box(a), black_box(b), P, barrett::MU))
+    });
+
+    group.bench_function("mul_f64_random", |bencher| {
+        bencher.iter(|| barrett::mul_f64(black_box(a), black_box(b), U52_P, barrett::U52_MU))
+    });
+
+    group.bench_function("mul_lazy_f64_random", |bencher| {
+        bencher.iter(|| barrett::mul_lazy_f64(black_box(a), black_box(b), U52_P, barrett::U52_MU))
+    });
+
+    group.finish();
+}
+
+fn bench_small_field(c: &mut Criterion) {
+    let mut group = c.benchmark_group("SmallField");
+
+    // Generate and print a random seed
+    let seed: u64 = rand::random();
+    println!("Using random seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let a = rng.random_range(0..BabyBear::P);
+    let b = rng.random_range(0..BabyBear::P);
+    let va = Simd::from_array(std::array::from_fn(|_| rng.random_range(0..BabyBear::P)));
+    let vb = Simd::from_array(std::array::from_fn(|_| rng.random_range(0..BabyBear::P)));
+
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("baby_bear_mul", |bencher| {
+        bencher.iter(|| monty31::mul::<BabyBear>(black_box(a), black_box(b)))
+    });
+    group.bench_function("koala_bear_mul", |bencher| {
+        bencher.iter(|| monty31::mul::<KoalaBear>(black_box(a), black_box(b)))
+    });
+    group.bench_function("mersenne31_mul", |bencher| {
+        bencher.iter(|| mersenne31::mul(black_box(a), black_box(b)))
+    });
+
+    group.throughput(Throughput::Elements(4));
+    group.bench_function("baby_bear_mul_simd", |bencher| {
+        bencher.iter(|| monty31::mul_simd::<BabyBear, 4>(black_box(va), black_box(vb)))
+    });
+    group.bench_function("koala_bear_mul_simd", |bencher| {
+        bencher.iter(|| monty31::mul_simd::<KoalaBear, 4>(black_box(va), black_box(vb)))
+    });
+    group.bench_function("mersenne31_mul_simd", |bencher| {
+        bencher.iter(|| mersenne31::mul_simd::<4>(black_box(va), black_box(vb)))
+    });
+
+    let a = rng.random_range(0..goldilocks::P);
+    let b = rng.random_range(0..goldilocks::P);
+    let va = Simd::from_array(std::array::from_fn(|_| rng.random_range(0..goldilocks::P)));
+    let vb = Simd::from_array(std::array::from_fn(|_| rng.random_range(0..goldilocks::P)));
+
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("goldilocks_mul", |bencher| {
+        bencher.iter(|| goldilocks::mul(black_box(a), black_box(b)))
+    });
+    group.throughput(Throughput::Elements(2));
+    group.bench_function("goldilocks_mul_simd", |bencher| {
+        bencher.iter(|| goldilocks::mul_simd::<2>(black_box(va), black_box(vb)))
+    });
+
+    group.finish();
+}
+
 fn bench_fpcr(c: &mut Criterion) {
     let mut group = c.benchmark_group("fpcr");
 
@@ -467,6 +560,7 @@ criterion_group!(
         // Warm up is warm because it literally warms up the pi
         .warm_up_time(std::time::Duration::new(1,0))
         .measurement_time(std::time::Duration::new(10,0));
-    targets = bench_acar, bench_emmart, bench_domb, bench_fpcr, bench_interleaved
+    targets = bench_acar, bench_emmart, bench_barrett, bench_domb, bench_fpcr, bench_interleaved,
+        bench_small_field
 );
 criterion_main!(benches);
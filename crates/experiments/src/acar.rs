@@ -55,7 +55,18 @@ fn naive(a: U256, b: U256, n: U256, np: U256) -> Vec<u64> {
 // SOS is like the naive version by fusing t + m*n
 // benefit is that we can calculate a single m
 pub fn sos(a: U256, b: U256, n: U256, np0: u64) -> [u64; 8] {
-    let mut t = [0_u64; 8];
+    sos_n(a, b, n, np0)
+}
+
+/// [`sos`] for `N` limbs, the output `T` has to be 2N limbs
+pub fn sos_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == 2 * N, "sos requires 2N output limbs") };
+    let mut t = [0_u64; T];
 
     // multiplication a * b
     for i in 0..a.len() {
@@ -89,7 +100,18 @@ pub fn sos(a: U256, b: U256, n: U256, np0: u64) -> [u64; 8] {
 // - and due to shifting we have a fresh spot for the last carry by which we don't need
 // adds
 pub fn cios(a: U256, b: U256, n: U256, np0: u64) -> [u64; 6] {
-    let mut t = [0_u64; 6];
+    cios_n(a, b, n, np0)
+}
+
+/// [`cios`] for `N` limbs, the output `T` has to be N + 2 limbs
+pub fn cios_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == N + 2, "cios requires N + 2 output limbs") };
+    let mut t = [0_u64; T];
     for i in 0..a.len() {
         let mut carry = 0;
         for j in 0..b.len() {
@@ -115,7 +137,18 @@ pub fn cios(a: U256, b: U256, n: U256, np0: u64) -> [u64; 6] {
 
 // cios_opt is cios where the division is combined with the multiplication
 pub fn cios_opt(a: U256, b: U256, n: U256, np0: u64) -> [u64; 6] {
-    let mut t = [0_u64; 6];
+    cios_opt_n(a, b, n, np0)
+}
+
+/// [`cios_opt`] for `N` limbs, the output `T` has to be N + 2 limbs
+pub fn cios_opt_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == N + 2, "cios_opt requires N + 2 output limbs") };
+    let mut t = [0_u64; T];
     for i in 0..a.len() {
         let mut carry = 0;
         for j in 0..b.len() {
@@ -205,7 +238,18 @@ pub fn cios_opt_sat(a: U256, b: U256, c: U256, d: U256, n: U256, np0: u64) -> [[
 // Due to this fusion the there are too many adds to fill the free spaces after multiplication
 // Therefore adds is needed again
 pub fn fios(a: U256, b: U256, n: U256, np0: u64) -> [u64; 6] {
-    let mut t = [0_u64; 6];
+    fios_n(a, b, n, np0)
+}
+
+/// [`fios`] for `N` limbs, the output `T` has to be N + 2 limbs
+pub fn fios_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == N + 2, "fios requires N + 2 output limbs") };
+    let mut t = [0_u64; T];
     for i in 0..a.len() {
         let (sum, mut carry) = carrying_mul_add(a[i], b[0], t[0], 0);
         adds(&mut t[1..], carry);
@@ -233,9 +277,12 @@ mod tests {
     use crate::arith::{modulus, subtraction_step};
     use block_multiplier::{
         constants::{NP0, P, R2},
+        derivation::neg_inv_mod_2_64,
         subarray,
     };
+    use num_bigint::BigUint;
     use quickcheck_macros::quickcheck;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     use mod256_generator::U256b64;
 
@@ -294,4 +341,72 @@ mod tests {
 
         res[0][..5] == cios_opt(a, b, P, NP0)[..5] && res[1][..5] == cios_opt(c, d, P, NP0)[..5]
     }
+
+    // Generic limb counts are checked against num_bigint instead of cios
+
+    fn to_limbs<const N: usize>(a: &BigUint) -> [u64; N] {
+        let mut digits = a.iter_u64_digits();
+        std::array::from_fn(|_| digits.next().unwrap_or(0))
+    }
+
+    fn from_limbs(limbs: &[u64]) -> BigUint {
+        limbs
+            .iter()
+            .rev()
+            .fold(BigUint::ZERO, |acc, limb| (acc << 64) + limb)
+    }
+
+    fn check_montgomery<const N: usize>(
+        n: &BigUint,
+        mont_mul: impl Fn([u64; N], [u64; N], [u64; N], u64) -> BigUint,
+    ) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let r_inv = (BigUint::from(1_u8) << (64 * N)).modinv(n).unwrap();
+        let n_limbs = to_limbs::<N>(n);
+        let np0 = neg_inv_mod_2_64(n_limbs[0]);
+
+        for _ in 0..1000 {
+            let a = from_limbs(&rng.random::<[u64; N]>()) % n;
+            let b = from_limbs(&rng.random::<[u64; N]>()) % n;
+            let out = mont_mul(to_limbs(&a), to_limbs(&b), n_limbs, np0);
+
+            assert!(out < n * 2_u8);
+            assert_eq!(out % n, a * b * &r_inv % n);
+        }
+    }
+
+    /// `T` = N + 2 and `S` = 2N
+    fn check_n<const N: usize, const T: usize, const S: usize>(n: &BigUint) {
+        check_montgomery::<N>(n, |a, b, n, np0| {
+            from_limbs(&sos_n::<N, S>(a, b, n, np0)[N..])
+        });
+        check_montgomery::<N>(n, |a, b, n, np0| {
+            from_limbs(&cios_n::<N, T>(a, b, n, np0)[..=N])
+        });
+        check_montgomery::<N>(n, |a, b, n, np0| {
+            from_limbs(&cios_opt_n::<N, T>(a, b, n, np0)[..=N])
+        });
+        check_montgomery::<N>(n, |a, b, n, np0| {
+            from_limbs(&fios_n::<N, T>(a, b, n, np0)[..=N])
+        });
+    }
+
+    #[test]
+    fn montgomery_4_limbs() {
+        check_n::<4, 6, 8>(&from_limbs(&P));
+    }
+
+    #[test]
+    fn montgomery_6_limbs() {
+        // BLS12-381 base field
+        let n = BigUint::parse_bytes(b"1a0111ea397fe69a4b1ba7b6434bacd764774b84f38512bf6730d2a0f6b0f6241eabfffeb153ffffb9feffffffffaaab", 16).unwrap();
+        check_n::<6, 8, 12>(&n);
+    }
+
+    #[test]
+    fn montgomery_8_limbs() {
+        // SOS drops the carry out of 2N limbs, hence a modulus below 2^511
+        let n = (BigUint::from(1_u8) << 511) - 187_u8;
+        check_n::<8, 10, 16>(&n);
+    }
 }
@@ -0,0 +1,344 @@
+use crate::acar::U256;
+use crate::arith::{carrying_mul_add, school_method, subtraction_step};
+use crate::emmart::{C1, C2, C3};
+use block_multiplier::constants::{MASK52, P};
+use block_multiplier::derivation::u256_to_u260;
+use block_multiplier::scalar::u260_to_u256;
+
+// Barrett reduction (HAC 14.42) of the full product a*b, as opposed to the Montgomery variants in
+// the other modules. The inputs and outputs are canonical, no conversion into and out of
+// Montgomery form is needed. The moduli are assumed to be below 2^254 such that 4P fits in 256
+// bits.
+//
+// With k words of radix b and mu = floor(b^2k / n)
+// - q1 = floor(x / b^(k-1))
+// - q3 = floor(q1 * mu / b^(k+1)), which is at most 2 below floor(x / n)
+// - r = x - q3 * n < 3n, which can be computed mod b^(k+1)
+// The lazy variants only compute the partial products of q1 * mu at positions k - 1 and higher.
+// This makes q3 at most 3 below floor(x / n), and the final subtractions are skipped, leaving
+// r < 4n. As any x < b^2k is valid, the lazy outputs can be multiplied again without reduction.
+
+/// floor(2^512 / P) for the 64 bit variants
+pub const MU: [u64; 5] = pow2_div(512, P);
+/// floor(2^520 / P) in 52 bit limbs for the float variants
+pub const U52_MU: [u64; 6] = u320_to_u52(pow2_div(520, P));
+
+pub fn mul(a: U256, b: U256, n: U256, mu: [u64; 5]) -> U256 {
+    let r = mul_lazy_inner::<0>(a, b, n, mu);
+    subtraction_step(subtraction_step(r, n), n)
+}
+
+/// Result below 4n, the inputs can be any 256 bit values
+pub fn mul_lazy(a: U256, b: U256, n: U256, mu: [u64; 5]) -> U256 {
+    mul_lazy_inner::<3>(a, b, n, mu)
+}
+
+/// Partial products of q1 * mu below position `SKIP` are skipped
+#[inline(always)]
+fn mul_lazy_inner<const SKIP: usize>(a: U256, b: U256, n: U256, mu: [u64; 5]) -> U256 {
+    let x = school_method(a, b);
+    let q1 = [x[3], x[4], x[5], x[6], x[7]];
+
+    let mut q2 = [0_u64; 10];
+    for i in 0..q1.len() {
+        let mut carry = 0;
+        for j in SKIP.saturating_sub(i)..mu.len() {
+            (q2[i + j], carry) = carrying_mul_add(q1[i], mu[j], q2[i + j], carry);
+        }
+        q2[i + mu.len()] = carry;
+    }
+    let q3 = [q2[5], q2[6], q2[7], q2[8], q2[9]];
+
+    // q3 * n mod b^5
+    let mut q3n = [0_u64; 5];
+    for i in 0..q3.len() {
+        let mut carry = 0;
+        for j in 0..(q3n.len() - i).min(n.len()) {
+            (q3n[i + j], carry) = carrying_mul_add(q3[i], n[j], q3n[i + j], carry);
+        }
+        if i + n.len() < q3n.len() {
+            q3n[i + n.len()] = carry;
+        }
+    }
+
+    // r = x - q3 * n mod b^5, which is below 4n < 2^256 so the top word is dropped
+    let mut r = [0_u64; 4];
+    let mut borrow = false;
+    for i in 0..r.len() {
+        let (diff, b1) = x[i].overflowing_sub(q3n[i]);
+        let (diff, b2) = diff.overflowing_sub(borrow as u64);
+        r[i] = diff;
+        borrow = b1 | b2;
+    }
+    r
+}
+
+// -- [52 BIT FLOAT] -------------------------------------------------------------------------------
+// Same algorithm with k = 5 words of 52 bits. The partial products are split into their high and
+// low 52 bits with FMA, see emmart, and accumulated per column before resolving the carries. Make
+// sure to call set_round_to_zero before using these functions.
+
+/// `n` and `mu` are in 52 bit limbs
+pub fn mul_f64(a: U256, b: U256, n: [u64; 5], mu: [u64; 6]) -> U256 {
+    let n64 = u260_to_u256(n);
+    let r = mul_lazy_f64_inner::<0>(a, b, n, mu);
+    subtraction_step(subtraction_step(r, n64), n64)
+}
+
+/// Result below 4n, the inputs can be any 256 bit values
+pub fn mul_lazy_f64(a: U256, b: U256, n: [u64; 5], mu: [u64; 6]) -> U256 {
+    mul_lazy_f64_inner::<4>(a, b, n, mu)
+}
+
+#[inline(always)]
+fn mul_lazy_f64_inner<const SKIP: usize>(a: U256, b: U256, n: [u64; 5], mu: [u64; 6]) -> U256 {
+    let a = u256_to_u260(a);
+    let b = u256_to_u260(b);
+
+    let mut x = [0_u64; 10];
+    for i in 0..a.len() {
+        for j in 0..b.len() {
+            let (lo, hi) = fma_mul(a[i], b[j]);
+            x[i + j] += lo;
+            x[i + j + 1] += hi;
+        }
+    }
+    let x = resolve(x);
+    let q1 = [x[4], x[5], x[6], x[7], x[8], x[9]];
+
+    let mut q2 = [0_u64; 12];
+    for i in 0..q1.len() {
+        for j in SKIP.saturating_sub(i)..mu.len() {
+            let (lo, hi) = fma_mul(q1[i], mu[j]);
+            q2[i + j] += lo;
+            q2[i + j + 1] += hi;
+        }
+    }
+    let q2 = resolve(q2);
+    let q3 = [q2[6], q2[7], q2[8], q2[9], q2[10], q2[11]];
+
+    // q3 * n mod b^5, the carries are resolved in the subtraction
+    let mut q3n = [0_u64; 5];
+    for i in 0..q3n.len() {
+        for j in 0..(q3n.len() - i).min(n.len()) {
+            let (lo, hi) = fma_mul(q3[i], n[j]);
+            q3n[i + j] += lo;
+            if i + j + 1 < q3n.len() {
+                q3n[i + j + 1] += hi;
+            }
+        }
+    }
+
+    // r = x - q3 * n mod b^5, exact as r < 4n < 2^256 < b^5
+    let mut r = [0_u64; 5];
+    let mut borrow: i64 = 0;
+    for i in 0..r.len() {
+        let tmp = x[i] as i64 - q3n[i] as i64 + borrow;
+        r[i] = tmp as u64 & MASK52;
+        borrow = tmp >> 52;
+    }
+    u260_to_u256(r)
+}
+
+/// Low and high 52 bits of the product of two values below 2^52
+#[inline(always)]
+fn fma_mul(a: u64, b: u64) -> (u64, u64) {
+    let p_hi = (a as f64).mul_add(b as f64, C1);
+    let p_lo = (a as f64).mul_add(b as f64, C2 - p_hi);
+    (p_lo.to_bits() - C3.to_bits(), p_hi.to_bits() - C1.to_bits())
+}
+
+#[inline(always)]
+fn resolve<const N: usize>(mut t: [u64; N]) -> [u64; N] {
+    let mut carry = 0;
+    for limb in &mut t {
+        let tmp = *limb + carry;
+        *limb = tmp & MASK52;
+        carry = tmp >> 52;
+    }
+    t
+}
+// -------------------------------------------------------------------------------------------------
+
+/// floor(2^k / n) by binary long division, for quotients below 2^320
+const fn pow2_div(k: usize, n: U256) -> [u64; 5] {
+    let mut q = [0_u64; 5];
+    // The remainder stays below 2n and needs a fifth word when doubled
+    let mut r = [0_u64; 5];
+    let mut i = k + 1;
+    while i > 0 {
+        i -= 1;
+        // r = 2r + bit i of 2^k
+        let mut w = r.len();
+        while w > 1 {
+            w -= 1;
+            r[w] = (r[w] << 1) | (r[w - 1] >> 63);
+        }
+        r[0] = (r[0] << 1) | (i == k) as u64;
+
+        if !lt(r, n) {
+            let mut borrow = 0;
+            let mut w = 0;
+            while w < r.len() {
+                let nw = if w < n.len() { n[w] } else { 0 };
+                let (diff, b1) = r[w].overflowing_sub(nw);
+                let (diff, b2) = diff.overflowing_sub(borrow);
+                r[w] = diff;
+                borrow = (b1 | b2) as u64;
+                w += 1;
+            }
+            assert!(i < 320, "quotient too large");
+            q[i / 64] |= 1 << (i % 64);
+        }
+    }
+    q
+}
+
+const fn lt(r: [u64; 5], n: U256) -> bool {
+    if r[4] != 0 {
+        return false;
+    }
+    let mut w = n.len();
+    while w > 0 {
+        w -= 1;
+        if r[w] != n[w] {
+            return r[w] < n[w];
+        }
+    }
+    false
+}
+
+const fn u320_to_u52(limbs: [u64; 5]) -> [u64; 6] {
+    let mut out = [0_u64; 6];
+    let mut i = 0;
+    while i < out.len() {
+        let bit = 52 * i;
+        let (w, s) = (bit / 64, bit % 64);
+        let mut limb = limbs[w] >> s;
+        if s > 12 && w + 1 < limbs.len() {
+            limb |= limbs[w + 1] << (64 - s);
+        }
+        out[i] = limb & MASK52;
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acar::cios_opt;
+    use crate::arith::modulus;
+    use crate::emmart::set_round_to_zero;
+    use block_multiplier::{
+        constants::{NP0, R2, U52_P},
+        subarray,
+    };
+    use mod256_generator::U256b64;
+    use num_bigint::BigUint;
+    use quickcheck_macros::quickcheck;
+
+    /// a * b mod P through Montgomery multiplication
+    fn cios_opt_mul(a: U256, b: U256) -> U256 {
+        let ab: U256 = subarray!(cios_opt(a, b, P, NP0), 0, 4);
+        modulus(subarray!(cios_opt(ab, R2, P, NP0), 0, 4), P)
+    }
+
+    fn below_four_p(r: U256) -> bool {
+        let four_p = [
+            P[0] << 2,
+            (P[1] << 2) | (P[0] >> 62),
+            (P[2] << 2) | (P[1] >> 62),
+            (P[3] << 2) | (P[2] >> 62),
+        ];
+        r.iter().rev().lt(four_p.iter().rev())
+    }
+
+    #[test]
+    fn mu() {
+        let to_biguint = |limbs: &[u64]| {
+            BigUint::from_slice(
+                &limbs
+                    .iter()
+                    .flat_map(|&l| [l as u32, (l >> 32) as u32])
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let p = to_biguint(&P);
+        assert_eq!(to_biguint(&MU), (BigUint::from(1_u8) << 512) / &p);
+
+        let u52_mu = U52_MU
+            .iter()
+            .rev()
+            .fold(BigUint::ZERO, |acc, &l| (acc << 52) + l);
+        assert_eq!(u52_mu, (BigUint::from(1_u8) << 520) / &p);
+    }
+
+    #[quickcheck]
+    fn barrett_ciosopt(a: U256b64, b: U256b64) -> bool {
+        let a = modulus(a.0, P);
+        let b = modulus(b.0, P);
+
+        mul(a, b, P, MU) == cios_opt_mul(a, b)
+    }
+
+    #[quickcheck]
+    fn barrett_lazy_ciosopt(a: U256b64, b: U256b64) -> bool {
+        let r = mul_lazy(a.0, b.0, P, MU);
+
+        below_four_p(r) && modulus(r, P) == cios_opt_mul(modulus(a.0, P), modulus(b.0, P))
+    }
+
+    #[quickcheck]
+    fn barrett_f64_ciosopt(a: U256b64, b: U256b64) -> bool {
+        set_round_to_zero();
+        let a = modulus(a.0, P);
+        let b = modulus(b.0, P);
+
+        mul_f64(a, b, U52_P, U52_MU) == cios_opt_mul(a, b)
+    }
+
+    #[quickcheck]
+    fn barrett_lazy_f64_ciosopt(a: U256b64, b: U256b64) -> bool {
+        set_round_to_zero();
+        let r = mul_lazy_f64(a.0, b.0, U52_P, U52_MU);
+
+        below_four_p(r) && modulus(r, P) == cios_opt_mul(modulus(a.0, P), modulus(b.0, P))
+    }
+
+    #[quickcheck]
+    fn barrett_lazy_chain(a: U256b64, b: U256b64) -> bool {
+        let a = modulus(a.0, P);
+        let b = modulus(b.0, P);
+        let mut lazy = a;
+        let mut expected = a;
+        for _ in 0..10 {
+            lazy = mul_lazy(lazy, b, P, MU);
+            expected = mul(expected, b, P, MU);
+        }
+
+        modulus(lazy, P) == expected
+    }
+
+    #[test]
+    fn barrett_edge_cases() {
+        set_round_to_zero();
+        let p_minus_one = [P[0] - 1, P[1], P[2], P[3]];
+        for (a, b) in [
+            ([0; 4], p_minus_one),
+            (p_minus_one, p_minus_one),
+            ([1, 0, 0, 0], [1, 0, 0, 0]),
+        ] {
+            let expected = cios_opt_mul(a, b);
+            assert_eq!(mul(a, b, P, MU), expected);
+            assert_eq!(mul_f64(a, b, U52_P, U52_MU), expected);
+            assert_eq!(modulus(mul_lazy(a, b, P, MU), P), expected);
+            assert_eq!(modulus(mul_lazy_f64(a, b, U52_P, U52_MU), P), expected);
+        }
+        // Largest lazy inputs
+        let max = [u64::MAX; 4];
+        let expected = cios_opt_mul(modulus(max, P), modulus(max, P));
+        assert_eq!(modulus(mul_lazy(max, max, P, MU), P), expected);
+        assert_eq!(modulus(mul_lazy_f64(max, max, U52_P, U52_MU), P), expected);
+    }
+}
@@ -1,6 +1,7 @@
 use std::hint::black_box;
 
 use block_multiplier::constants::{U52_NP0, U52_P};
+use block_multiplier::rounding::RoundToZeroGuard;
 use montgomery_reduction::emmart;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
@@ -8,7 +9,8 @@ fn main() {
     let seed: u64 = rand::random();
     let mut rng = StdRng::seed_from_u64(seed);
 
-    emmart::set_round_to_zero();
+    // Restores the rounding mode when main returns
+    let _rounding = RoundToZeroGuard::new();
 
     let a = [
         rng.random::<u64>(),
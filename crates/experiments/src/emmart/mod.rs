@@ -7,7 +7,10 @@ use std::{
 use seq_macro::seq;
 
 use crate::acar;
-use block_multiplier::constants::{F52_P, MASK52, NP0, P, U52_NP0};
+use block_multiplier::constants::{F52_P, MASK52, NP0, P, U52_NP0, U52_P};
+#[cfg(target_arch = "aarch64")]
+pub use block_multiplier::rounding::set_fpcr;
+pub use block_multiplier::rounding::set_round_to_zero;
 
 /// Make sure to call set_round_to_zero before using any of the functions in this module
 pub mod paper;
@@ -43,64 +46,6 @@ pub fn modulus_u52<const N: usize>(a: [u64; N], b: [u64; N]) -> [u64; N] {
     d
 }
 
-#[cfg(target_arch = "aarch64")]
-#[inline(never)]
-/// Set the floating point rounding mode to round to zero
-///
-/// inline(never) to prevent to compiler from reordering
-pub fn set_round_to_zero() -> u64 {
-    let fpcr: u64;
-    unsafe {
-        // Set RMode (bits 22-23) to 0b11 for round toward zero
-        core::arch::asm!(
-            "mrs {fpcr}, fpcr",             // Read current FPCR
-            "orr {tmp}, {fpcr}, #0b11<<22", // Set RMode bits to 11 using bit shift notation
-            "msr fpcr, {tmp}",             // Write back to FPCR
-            tmp = out(reg) _,
-            fpcr = out(reg) fpcr,
-        );
-    }
-
-    // Defense-in-depth but can't be relied on
-    // From the documentation:
-    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
-    std::hint::black_box(fpcr)
-}
-
-#[cfg(not(target_arch = "aarch64"))]
-pub fn set_round_to_zero() -> u64 {
-    // No-op or panic depending on your needs for non-ARM platforms
-    unimplemented!("Round to zero is only implemented for ARM64");
-}
-
-#[cfg(target_arch = "aarch64")]
-#[inline(never)]
-/// Set the floating point control register (FPCR) to a specified value
-///
-/// This function allows direct control of the ARM64 FPCR register, which controls
-/// floating point behavior including rounding modes, exception handling, and other
-/// floating point settings.
-///
-/// inline(never) to prevent the compiler from reordering this operation
-pub fn set_fpcr(fpcr: u64) {
-    // Defense-in-depth but can't be relied on
-    // From the documentation:
-    // Programs cannot rely on black_box for correctness, beyond it behaving as the identity function. As such, it must not be relied upon to control critical program behavior.
-    std::hint::black_box(fpcr);
-    unsafe {
-        core::arch::asm!(
-            "msr fpcr, {fpcr}",
-            fpcr = in(reg) fpcr
-        )
-    }
-}
-
-#[cfg(not(target_arch = "aarch64"))]
-pub fn set_fpcr() -> u64 {
-    // No-op or panic depending on your needs for non-ARM platforms
-    unimplemented!("Round to zero is only implemented for ARM64");
-}
-
 /// Resolve into non-redundant form meaning that there are no carries in the
 /// high [52..] part
 #[inline(always)]
@@ -127,8 +72,8 @@ pub fn resolve_simd<const N: usize>(t: [Simd<u64, 2>; N]) -> [[u64; N]; 2] {
 }
 
 #[inline(always)]
-fn resolve_simd_sat(t: [Simd<u64, 4>; 6]) -> [[u64; 6]; 4] {
-    let mut out = [[0; 6]; 4];
+fn resolve_simd_sat<const N: usize>(t: [Simd<u64, 4>; N]) -> [[u64; N]; 4] {
+    let mut out = [[0; N]; 4];
     let mut carry = Simd::splat(0);
     for i in 0..t.len() {
         let tmp = t[i] + carry;
@@ -156,7 +101,18 @@ fn resolve_2(mut tx: [[u64; 6]; 2]) -> [[u64; 6]; 2] {
 }
 /// Based on CIOS_OPT from ACAR but with floating point multiplication
 pub fn cios_opt(a: [u64; 5], b: [u64; 5], n: [u64; 5], np0: u64) -> [u64; 6] {
-    let mut t = [0_u64; 6];
+    cios_opt_n(a, b, n, np0)
+}
+
+/// [`cios_opt`] for `N` limbs, the output `T` has to be N + 1 limbs
+pub fn cios_opt_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == N + 1, "cios_opt requires N + 1 output limbs") };
+    let mut t = [0_u64; T];
     for i in 0..a.len() {
         // a_i * B
         for j in 0..b.len() {
@@ -189,13 +145,27 @@ pub fn cios_opt(a: [u64; 5], b: [u64; 5], n: [u64; 5], np0: u64) -> [u64; 6] {
 /// Like cios_opt above but with the subtraction optimisation
 /// `t` is initialised with the - sum of exponents
 pub fn cios_opt_sub(a: [u64; 5], b: [u64; 5]) -> [u64; 6] {
-    let mut t = [0_u64; 6];
+    cios_opt_sub_n(a, b, U52_P, U52_NP0)
+}
+
+/// [`cios_opt_sub`] for `N` limbs and any modulus, the output `T` has to be N + 1 limbs
+///
+/// Only the lower N limbs of the output are significant, the result is below 2^52N for a
+/// modulus below 2^(52N - 1)
+pub fn cios_opt_sub_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == N + 1, "cios_opt_sub requires N + 1 output limbs") };
+    let mut t = [0_u64; T];
     for i in 0..t.len() - 1 {
         t[i] = make_initial(2 + 2 * i, 2 * i);
     }
 
     for i in 0..a.len() {
-        t[F52_P.len()] = make_initial(10 - 2 - 2 * i, 10 - 2 * i);
+        t[n.len()] = make_initial(2 * (n.len() - 1 - i), 2 * (n.len() - i));
         // a_i * B
         for j in 0..b.len() {
             let p_hi = (a[i] as f64).mul_add(b[j] as f64, C1);
@@ -204,20 +174,20 @@ pub fn cios_opt_sub(a: [u64; 5], b: [u64; 5]) -> [u64; 6] {
             t[j] = t[j].wrapping_add(p_lo.to_bits());
         }
 
-        let m = (t[0].wrapping_mul(U52_NP0) & MASK52) as f64;
+        let m = (t[0].wrapping_mul(np0) & MASK52) as f64;
         // Outside of the loop because the loop does shifting
-        let p_hi = m.mul_add(F52_P[0], C1);
-        let p_lo = m.mul_add(F52_P[0], C2 - p_hi);
+        let p_hi = m.mul_add(n[0] as f64, C1);
+        let p_lo = m.mul_add(n[0] as f64, C2 - p_hi);
         t[0] = t[0].wrapping_add(p_lo.to_bits());
         t[1] = t[1].wrapping_add((p_hi.to_bits()) + (t[0] >> 52));
 
-        for j in 1..F52_P.len() {
-            let p_hi = m.mul_add(F52_P[j], C1);
-            let p_lo = m.mul_add(F52_P[j], C2 - p_hi);
+        for j in 1..n.len() {
+            let p_hi = m.mul_add(n[j] as f64, C1);
+            let p_lo = m.mul_add(n[j] as f64, C2 - p_hi);
             t[j + 1] = t[j + 1].wrapping_add(p_hi.to_bits());
             t[j - 1] = t[j].wrapping_add(p_lo.to_bits());
         }
-        t[F52_P.len() - 1] = t[F52_P.len()];
+        t[n.len() - 1] = t[n.len()];
     }
 
     resolve(t)
@@ -337,7 +307,21 @@ pub fn fios_opt_sub_sat(
 // Batch all the subtractions on t[i] together
 // Best performing f64 version on the RPi
 pub fn fios_opt_sub(a: [u64; 5], b: [u64; 5], n: [u64; 5], np0: u64) -> [u64; 6] {
-    let mut t = [0_u64; 6];
+    fios_opt_sub_n(a, b, n, np0)
+}
+
+/// [`fios_opt_sub`] for `N` limbs, the output `T` has to be N + 1 limbs
+///
+/// Only the lower N limbs of the output are significant, the result is below 2^52N for a
+/// modulus below 2^(52N - 1)
+pub fn fios_opt_sub_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == N + 1, "fios_opt_sub requires N + 1 output limbs") };
+    let mut t = [0_u64; T];
     for i in 0..t.len() - 1 {
         t[i] = make_initial(2 + 2 * i, 2 * i);
     }
@@ -447,7 +431,32 @@ pub fn fios_opt_sub_simd_sat(
     n: [u64; 5],
     np0: u64,
 ) -> [[u64; 6]; 4] {
-    let mut t: [Simd<u64, 4>; 6] = [Simd::splat(0); 6];
+    fios_opt_sub_simd_sat_n(a, b, c, d, e, f, g, h, n, np0)
+}
+
+/// [`fios_opt_sub_simd_sat`] for `N` limbs, the outputs `T` have to be N + 1 limbs
+///
+/// Only the lower N limbs of the output are significant, the result is below 2^52N for a
+/// modulus below 2^(52N - 1)
+pub fn fios_opt_sub_simd_sat_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    c: [u64; N],
+    d: [u64; N],
+    e: [u64; N],
+    f: [u64; N],
+    g: [u64; N],
+    h: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [[u64; T]; 4] {
+    const {
+        assert!(
+            T == N + 1,
+            "fios_opt_sub_simd_sat requires N + 1 output limbs"
+        )
+    };
+    let mut t: [Simd<u64, 4>; T] = [Simd::splat(0); T];
     for i in 0..t.len() - 1 {
         t[i] = Simd::splat(make_initial(2 + 2 * i, 2 * i))
     }
@@ -622,7 +631,18 @@ pub fn fios_opt_sub_simd_seq(
 
 /// FIOS variant with the subtraction optimization
 pub fn fios_opt(a: [u64; 5], b: [u64; 5], n: [u64; 5], np0: u64) -> [u64; 6] {
-    let mut t = [0_u64; 6];
+    fios_opt_n(a, b, n, np0)
+}
+
+/// [`fios_opt`] for `N` limbs, the output `T` has to be N + 1 limbs
+pub fn fios_opt_n<const N: usize, const T: usize>(
+    a: [u64; N],
+    b: [u64; N],
+    n: [u64; N],
+    np0: u64,
+) -> [u64; T] {
+    const { assert!(T == N + 1, "fios_opt requires N + 1 output limbs") };
+    let mut t = [0_u64; T];
 
     for i in 0..a.len() {
         // a_i * B
@@ -666,7 +686,7 @@ const fn pow_2(n: u32) -> f64 {
 
 pub const C1: f64 = pow_2(104); // 2.0^104
 pub const C2: f64 = pow_2(104) + pow_2(52); // 2.0^104 + 2.0^52
-const C3: f64 = pow_2(52); // 2.0^52
+pub const C3: f64 = pow_2(52); // 2.0^52
 
 #[inline]
 pub const fn make_initial(low_count: usize, high_count: usize) -> u64 {
@@ -679,9 +699,12 @@ mod tests {
     use crate::arith;
     use crate::emmart::modulus_u52;
     use crate::emmart::subtraction_step_u52;
-    use block_multiplier::constants::{P, R2, U52_NP0, U52_P, U52_R2};
+    use block_multiplier::constants::{MASK52, P, R2, U52_NP0, U52_P, U52_R2};
+    use block_multiplier::derivation::neg_inv_mod_2_64;
     use block_multiplier::subarray;
     use mod256_generator::{U256b52, U256b64};
+    use num_bigint::BigUint;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     use super::set_round_to_zero;
     use quickcheck_macros::quickcheck;
@@ -832,4 +855,89 @@ mod tests {
 
         modulus_u52(a.0, U52_P) == subtraction_step_u52(subarray!(a_round, 0, 5), U52_P)
     }
+
+    // Generic limb counts are checked against num_bigint
+
+    fn to_limbs<const N: usize>(a: &BigUint) -> [u64; N] {
+        std::array::from_fn(|i| {
+            let limb = (a >> (52 * i)) & BigUint::from(MASK52);
+            limb.try_into().unwrap()
+        })
+    }
+
+    fn from_limbs(limbs: &[u64]) -> BigUint {
+        limbs
+            .iter()
+            .rev()
+            .fold(BigUint::ZERO, |acc, limb| (acc << 52) + limb)
+    }
+
+    fn check_montgomery<const N: usize>(
+        n: &BigUint,
+        mont_mul: impl Fn([[u64; N]; 4], [[u64; N]; 4], [u64; N], u64) -> [BigUint; 4],
+    ) {
+        set_round_to_zero();
+        let mut rng = StdRng::seed_from_u64(0);
+        let r_inv = (BigUint::from(1_u8) << (52 * N)).modinv(n).unwrap();
+        let n_limbs = to_limbs::<N>(n);
+        let np0 = neg_inv_mod_2_64(n_limbs[0]) & MASK52;
+
+        for _ in 0..1000 {
+            let a: [BigUint; 4] =
+                std::array::from_fn(|_| BigUint::from_slice(&rng.random::<[u32; 32]>()) % n);
+            let b: [BigUint; 4] =
+                std::array::from_fn(|_| BigUint::from_slice(&rng.random::<[u32; 32]>()) % n);
+            let out = mont_mul(
+                a.each_ref().map(to_limbs),
+                b.each_ref().map(to_limbs),
+                n_limbs,
+                np0,
+            );
+
+            for i in 0..4 {
+                assert!(out[i] < n * 2_u8);
+                assert_eq!(&out[i] % n, &a[i] * &b[i] * &r_inv % n);
+            }
+        }
+    }
+
+    /// `T` = N + 1, of which the subtraction variants leave a copy of limb N - 1 in limb N
+    fn check_n<const N: usize, const T: usize>(n: &BigUint) {
+        let lanes = |f: fn([u64; N], [u64; N], [u64; N], u64) -> [u64; T]| {
+            move |a: [[u64; N]; 4], b: [[u64; N]; 4], n, np0| {
+                std::array::from_fn(|i| from_limbs(&f(a[i], b[i], n, np0)[..N]))
+            }
+        };
+        check_montgomery::<N>(n, lanes(super::cios_opt_n::<N, T>));
+        check_montgomery::<N>(n, lanes(super::cios_opt_sub_n::<N, T>));
+        check_montgomery::<N>(n, lanes(super::fios_opt_sub_n::<N, T>));
+        check_montgomery::<N>(n, lanes(super::fios_opt_n::<N, T>));
+        check_montgomery::<N>(n, |a, b, n, np0| {
+            super::fios_opt_sub_simd_sat_n::<N, T>(
+                a[0], b[0], a[1], b[1], a[2], b[2], a[3], b[3], n, np0,
+            )
+            .map(|out| from_limbs(&out[..N]))
+        });
+    }
+
+    #[test]
+    fn montgomery_4_limbs() {
+        // NIST P-192
+        let n = (BigUint::from(1_u8) << 192) - (BigUint::from(1_u8) << 64) - 1_u8;
+        check_n::<4, 5>(&n);
+    }
+
+    #[test]
+    fn montgomery_6_limbs() {
+        // secp256k1 base field
+        let n = (BigUint::from(1_u8) << 256) - (BigUint::from(1_u8) << 32) - 977_u16;
+        check_n::<6, 7>(&n);
+    }
+
+    #[test]
+    fn montgomery_8_limbs() {
+        // BLS12-381 base field
+        let n = BigUint::parse_bytes(b"1a0111ea397fe69a4b1ba7b6434bacd764774b84f38512bf6730d2a0f6b0f6241eabfffeb153ffffb9feffffffffaaab", 16).unwrap();
+        check_n::<8, 9>(&n);
+    }
 }
@@ -343,8 +343,8 @@ pub fn parallel_simd_sub(a: [[u64; 5]; 2], b: [[u64; 5]; 2]) -> [[u64; 5]; 2] {
 // -------------------------------------------------------------------------------------------------
 
 pub struct InterleavedRes {
-    s0: [u64; 4],
-    v0: [[u64; 5]; 2],
+    pub s0: [u64; 4],
+    pub v0: [[u64; 5]; 2],
 }
 
 pub fn interleaved(
@@ -3,7 +3,10 @@
 
 pub mod acar;
 pub mod arith;
+pub mod barrett;
 pub mod domb;
 pub mod emmart;
 pub mod interleaved;
+pub mod small_field;
+pub mod tuner;
 pub mod yuval;
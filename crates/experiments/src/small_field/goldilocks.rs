@@ -0,0 +1,90 @@
+use std::simd::{
+    cmp::{SimdPartialEq, SimdPartialOrd},
+    Select, Simd,
+};
+
+use block_multiplier::goldilocks::widening_mul_simd;
+pub use block_multiplier::goldilocks::P;
+
+/// R^2 mod P with R = 2^64 ≡ 2^32 - 1, multiplying by it converts into Montgomery form
+pub const R2: u64 = ((0xffffffff_u128 * 0xffffffff) % P as u128) as u64;
+
+// a * b * 2^-64 mod P for a, b < P. The output is below P.
+#[inline(always)]
+pub fn mul(a: u64, b: u64) -> u64 {
+    let t = a as u128 * b as u128;
+    let (lo, hi) = (t as u64, (t >> 64) as u64);
+
+    // m = lo P^-1 mod 2^64 with P^-1 = 2^32 + 1
+    let m = lo.wrapping_add(lo << 32);
+    // m P = m 2^64 - y with y = m (2^32 - 1) < 2^96. The lower half of m P is lo, so y has a
+    // nonzero lower half exactly when lo is nonzero and rounding y up is a comparison.
+    let y_hi = (m >> 32) - ((m << 32) < m) as u64;
+    let mp_hi = m - y_hi - (lo != 0) as u64;
+
+    // (t - m P) / 2^64 is in (-P, P)
+    let (r, borrow) = hi.overflowing_sub(mp_hi);
+    if borrow {
+        r.wrapping_add(P)
+    } else {
+        r
+    }
+}
+
+// mul for every lane
+#[inline(always)]
+pub fn mul_simd<const L: usize>(a: Simd<u64, L>, b: Simd<u64, L>) -> Simd<u64, L> {
+    let zero = Simd::splat(0);
+    let one = Simd::splat(1);
+    let (lo, hi) = widening_mul_simd(a, b);
+
+    // See mul
+    let m = lo + (lo << 32);
+    let y_hi = (m >> 32) - (m << 32).simd_lt(m).select(one, zero);
+    let mp_hi = m - y_hi - lo.simd_ne(zero).select(one, zero);
+
+    let r = hi - mp_hi;
+    hi.simd_lt(mp_hi).select(r + Simd::splat(P), r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+    use rand::{rngs, Rng, SeedableRng};
+
+    #[test]
+    fn test_mul() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = BigUint::from(P);
+        // 2^-64 mod P
+        let r_inv = BigUint::from(2u32).modpow(&(&p - 2u32), &p).pow(64) % &p;
+
+        let edge = [0, 1, 2, u32::MAX as u64, 1 << 32, 1 << 63, P - 2, P - 1];
+        let mut pairs: Vec<(u64, u64)> = (0..10000)
+            .map(|_| (rng.random_range(0..P), rng.random_range(0..P)))
+            .collect();
+        for a in edge {
+            for b in edge {
+                pairs.push((a, b));
+            }
+        }
+
+        for chunk in pairs.chunks_exact(2) {
+            let a = Simd::from_array([chunk[0].0, chunk[1].0]);
+            let b = Simd::from_array([chunk[0].1, chunk[1].1]);
+            let v = mul_simd::<2>(a, b).to_array();
+            for (i, &(a, b)) in chunk.iter().enumerate() {
+                let expected = BigUint::from(a) * b * &r_inv % &p;
+                assert_eq!(BigUint::from(mul(a, b)), expected, "{a:#x} * {b:#x}");
+                assert_eq!(BigUint::from(v[i]), expected, "{a:#x} * {b:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let a = 0x123456789abcdef;
+        assert_eq!(mul(mul(a, R2), 1), a);
+    }
+}
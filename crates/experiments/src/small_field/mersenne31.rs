@@ -0,0 +1,61 @@
+use std::simd::{cmp::SimdPartialOrd, num::SimdUint, Select, Simd};
+
+/// 2^31 - 1
+pub const P: u32 = 0x7fffffff;
+
+// a * b mod P for a, b < P. With 2^31 ≡ 1 the 62 bit product folds onto itself, the output is
+// below P.
+#[inline(always)]
+pub fn mul(a: u32, b: u32) -> u32 {
+    let t = a as u64 * b as u64;
+    // Both halves are below 2^31 and t < P^2 keeps the sum below 2P
+    let r = (t as u32 & P) + (t >> 31) as u32;
+    if r >= P {
+        r - P
+    } else {
+        r
+    }
+}
+
+// mul for every lane
+#[inline(always)]
+pub fn mul_simd<const L: usize>(a: Simd<u32, L>, b: Simd<u32, L>) -> Simd<u32, L> {
+    let p = Simd::splat(P);
+    let t: Simd<u64, L> = a.cast::<u64>() * b.cast::<u64>();
+    let r = (t.cast::<u32>() & p) + (t >> 31).cast::<u32>();
+    r.simd_ge(p).select(r - p, r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+    use rand::{rngs, Rng, SeedableRng};
+
+    #[test]
+    fn test_mul() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = BigUint::from(P);
+
+        let edge = [0, 1, 2, 1 << 30, P - 2, P - 1];
+        let mut pairs: Vec<(u32, u32)> = (0..10000)
+            .map(|_| (rng.random_range(0..P), rng.random_range(0..P)))
+            .collect();
+        for a in edge {
+            for b in edge {
+                pairs.push((a, b));
+            }
+        }
+
+        for chunk in pairs.chunks_exact(4) {
+            let a = Simd::from_array(std::array::from_fn(|i| chunk[i].0));
+            let b = Simd::from_array(std::array::from_fn(|i| chunk[i].1));
+            let v = mul_simd::<4>(a, b).to_array();
+            for (i, &(a, b)) in chunk.iter().enumerate() {
+                let expected = BigUint::from(a) * b % &p;
+                assert_eq!(BigUint::from(mul(a, b)), expected, "{a:#x} * {b:#x}");
+                assert_eq!(BigUint::from(v[i]), expected, "{a:#x} * {b:#x}");
+            }
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! Packed multiplication for the 31 to 64 bit fields used by STARK provers.
+//!
+//! Every field has a scalar `mul` and a `mul_simd` over any lane count. `Simd<u32, 4>` and
+//! `Simd<u64, 2>` fill a NEON register, wider vectors are split by the compiler. Unlike the 256
+//! bit multipliers the products are exact in integer lanes, so no floating point and no rounding
+//! mode is involved: 32x32 bit products widen into 64 bit lanes (`umull` on NEON) and the 64 bit
+//! field builds its 128 bit products from those.
+//!
+//! - [`monty31`]: Montgomery multiplication for 31 bit primes, BabyBear and KoalaBear
+//! - [`mersenne31`]: 2^31 - 1, where 2^31 ≡ 1 makes the plain reduction cheaper than Montgomery
+//! - [`goldilocks`]: Montgomery multiplication for 2^64 - 2^32 + 1, whose P^-1 mod 2^64 is
+//!   2^32 + 1 such that the reduction needs no multiplications. [`block_multiplier::goldilocks`]
+//!   multiplies in canonical form instead, which needs one more multiplication per product.
+
+pub mod goldilocks;
+pub mod mersenne31;
+pub mod monty31;
@@ -0,0 +1,118 @@
+use std::simd::{cmp::SimdPartialOrd, num::SimdUint, Select, Simd};
+
+/// A prime below 2^31 with elements in Montgomery form with R = 2^32
+pub trait MontyField31 {
+    const P: u32;
+    /// -P^-1 mod 2^32
+    const MU: u32 = neg_inv_mod_2_32(Self::P);
+    /// R^2 mod P, multiplying by it converts into Montgomery form
+    const R2: u32 = ((1u128 << 64) % Self::P as u128) as u32;
+}
+
+/// 15 * 2^27 + 1
+pub struct BabyBear;
+
+impl MontyField31 for BabyBear {
+    const P: u32 = 0x78000001;
+}
+
+/// 2^31 - 2^24 + 1
+pub struct KoalaBear;
+
+impl MontyField31 for KoalaBear {
+    const P: u32 = 0x7f000001;
+}
+
+pub const fn neg_inv_mod_2_32(p: u32) -> u32 {
+    assert!(
+        p & 1 == 1 && p >> 31 == 0,
+        "modulus must be odd and below 2^31"
+    );
+    // Newton iteration doubles the number of correct bits. p is its own inverse mod 2^3.
+    let mut inv = p;
+    let mut i = 0;
+    while i < 4 {
+        inv = inv.wrapping_mul(2u32.wrapping_sub(p.wrapping_mul(inv)));
+        i += 1;
+    }
+    inv.wrapping_neg()
+}
+
+// a * b * 2^-32 mod P for a, b < P. The output is below P.
+#[inline(always)]
+pub fn mul<F: MontyField31>(a: u32, b: u32) -> u32 {
+    let t = a as u64 * b as u64;
+    let m = (t as u32).wrapping_mul(F::MU);
+    // t + m P < 2^62 + 2^63, the lower half is zero
+    let r = ((t + m as u64 * F::P as u64) >> 32) as u32;
+    if r >= F::P {
+        r - F::P
+    } else {
+        r
+    }
+}
+
+// mul for every lane
+#[inline(always)]
+pub fn mul_simd<F: MontyField31, const L: usize>(a: Simd<u32, L>, b: Simd<u32, L>) -> Simd<u32, L> {
+    let p = Simd::splat(F::P);
+    let t: Simd<u64, L> = a.cast::<u64>() * b.cast::<u64>();
+    let m = t.cast::<u32>() * Simd::splat(F::MU);
+    let r: Simd<u32, L> = ((t + m.cast::<u64>() * p.cast::<u64>()) >> 32).cast();
+    r.simd_ge(p).select(r - p, r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+    use rand::{rngs, Rng, SeedableRng};
+
+    fn check_mul<F: MontyField31>() {
+        let mut rng = rngs::StdRng::seed_from_u64(0);
+        let p = BigUint::from(F::P);
+        // 2^-32 mod P
+        let r_inv = BigUint::from(2u32).modpow(&(&p - 2u32), &p).pow(32) % &p;
+
+        let edge = [0, 1, 2, F::P / 2, F::P - 2, F::P - 1];
+        let mut pairs: Vec<(u32, u32)> = (0..10000)
+            .map(|_| (rng.random_range(0..F::P), rng.random_range(0..F::P)))
+            .collect();
+        for a in edge {
+            for b in edge {
+                pairs.push((a, b));
+            }
+        }
+
+        for chunk in pairs.chunks_exact(4) {
+            let a = Simd::from_array(std::array::from_fn(|i| chunk[i].0));
+            let b = Simd::from_array(std::array::from_fn(|i| chunk[i].1));
+            let v = mul_simd::<F, 4>(a, b).to_array();
+            for (i, &(a, b)) in chunk.iter().enumerate() {
+                let expected = BigUint::from(a) * b * &r_inv % &p;
+                assert_eq!(BigUint::from(mul::<F>(a, b)), expected, "{a:#x} * {b:#x}");
+                assert_eq!(BigUint::from(v[i]), expected, "{a:#x} * {b:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_mul_baby_bear() {
+        check_mul::<BabyBear>();
+    }
+
+    #[test]
+    fn test_mul_koala_bear() {
+        check_mul::<KoalaBear>();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let a = 0x1234567;
+        let a_mont = mul::<BabyBear>(a, BabyBear::R2);
+        assert_eq!(mul::<BabyBear>(a_mont, 1), a);
+        // -P^-1 P ≡ -1
+        assert_eq!(BabyBear::MU.wrapping_mul(BabyBear::P), u32::MAX);
+        assert_eq!(KoalaBear::MU.wrapping_mul(KoalaBear::P), u32::MAX);
+    }
+}
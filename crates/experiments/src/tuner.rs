@@ -0,0 +1,270 @@
+//! Runtime selection of the fastest Montgomery multiplication for the host.
+//!
+//! Every candidate wraps one of the kernels of this crate, or [`block_multiplier`], into a
+//! [`MulBatch`] on 4x64 bit limbs in Montgomery form (R = 2^256) for BN254's scalar field. Kernels
+//! on 52 bit limbs get their inputs shifted left by 2 bits, such that their 2^260 Montgomery radix
+//! results in the same 2^256 form. A candidate only takes part when its products agree with
+//! [`mont_mul`] and stay below [`FieldConfig::OUTPUT_MAX`] on a set of test vectors, which keeps
+//! the contract of [`block_multiplier::batch::mul_batch`] whichever candidate wins. The remaining
+//! candidates are timed on a batch and the fastest one serves [`mul_batch`] from then on.
+//!
+//! The measurement runs on the first call to [`mul_batch`], or in [`init`] which caches the
+//! choice in a file so that it runs once per host.
+
+use std::{
+    fs, io,
+    path::Path,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use block_multiplier::{
+    arith::reduce,
+    config::{Bn254Fr, FieldConfig},
+    constants::{NP0, P, U52_NP0, U52_P},
+    derivation::mont_mul,
+    rounding::RoundToZeroGuard,
+    scalar::{u256_to_u260_shl2, u260_to_u256},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{acar, domb, emmart, yuval};
+
+/// Element wise Montgomery multiplication `out[i] = a[i] * b[i]` of slices of the same length
+pub type MulBatch = fn(&[[u64; 4]], &[[u64; 4]], &mut [[u64; 4]]);
+
+pub struct Candidate {
+    pub name: &'static str,
+    pub mul_batch: MulBatch,
+}
+
+pub static CANDIDATES: [Candidate; 5] = [
+    Candidate {
+        name: "acar::cios_opt",
+        mul_batch: acar_cios_opt,
+    },
+    Candidate {
+        name: "emmart::fios_opt_sub_simd_sat_seq",
+        mul_batch: emmart_fios_opt_sub_simd_sat_seq,
+    },
+    Candidate {
+        name: "domb::parallel_sub_simd_r256",
+        mul_batch: domb_parallel_sub_simd_r256,
+    },
+    Candidate {
+        name: "yuval::parallel",
+        mul_batch: yuval_parallel,
+    },
+    Candidate {
+        name: "block_multiplier",
+        mul_batch: block_multiplier::batch::mul_batch::<Bn254Fr>,
+    },
+];
+
+/// Elements per timed batch
+const BATCH_SIZE: usize = 1 << 10;
+/// Timed runs per candidate, of which the median counts
+const RUNS: usize = 21;
+
+static SELECTED: OnceLock<&'static Candidate> = OnceLock::new();
+
+/// Element wise Montgomery multiplication `out[i] = a[i] * b[i]` with the fastest candidate.
+///
+/// Inputs below P give outputs below [`FieldConfig::OUTPUT_MAX`]. The first call selects the
+/// candidate unless [`init`] did. Panics if the slices don't have the same length.
+pub fn mul_batch(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    assert_eq!(a.len(), b.len(), "input lengths differ");
+    assert_eq!(a.len(), out.len(), "output length differs");
+
+    (selected().mul_batch)(a, b, out)
+}
+
+/// The candidate serving [`mul_batch`], selected by [`tune`] on first use
+pub fn selected() -> &'static Candidate {
+    SELECTED.get_or_init(tune)
+}
+
+/// Select the candidate named in the cache file, or run [`tune`] and store the winner in it.
+///
+/// A missing or unreadable file, an unknown name or a candidate that no longer passes [`validate`]
+/// lead to a new measurement. If a candidate has been selected already, it is kept and written to
+/// the file instead.
+pub fn init(cache: impl AsRef<Path>) -> io::Result<&'static Candidate> {
+    let contents = fs::read_to_string(&cache).ok();
+    let cached = contents.as_deref().map(str::trim);
+
+    let candidate = SELECTED.get_or_init(|| {
+        CANDIDATES
+            .iter()
+            .find(|candidate| Some(candidate.name) == cached)
+            .filter(|candidate| validate(candidate))
+            .unwrap_or_else(tune)
+    });
+    if cached != Some(candidate.name) {
+        fs::write(&cache, format!("{}\n", candidate.name))?;
+    }
+    Ok(candidate)
+}
+
+/// The fastest of the candidates that pass [`validate`]
+pub fn tune() -> &'static Candidate {
+    let mut rng = StdRng::seed_from_u64(0);
+    let a: Vec<[u64; 4]> = (0..BATCH_SIZE).map(|_| random_element(&mut rng)).collect();
+    let b: Vec<[u64; 4]> = (0..BATCH_SIZE).map(|_| random_element(&mut rng)).collect();
+    let mut out = vec![[0; 4]; BATCH_SIZE];
+
+    CANDIDATES
+        .iter()
+        .filter(|candidate| validate(candidate))
+        .min_by_key(|candidate| measure(candidate.mul_batch, &a, &b, &mut out))
+        .expect("block_multiplier is always correct")
+}
+
+/// Whether the candidate's products are congruent to [`mont_mul`] and below `OUTPUT_MAX`
+pub fn validate(candidate: &Candidate) -> bool {
+    let mut rng = StdRng::seed_from_u64(1);
+    let edge = [[0; 4], [1, 0, 0, 0], Bn254Fr::R, sub_one(P)];
+    let mut a: Vec<[u64; 4]> = edge.iter().flat_map(|&x| edge.map(|_| x)).collect();
+    let mut b: Vec<[u64; 4]> = edge.iter().flat_map(|_| edge).collect();
+    // Not a multiple of the number of lanes of any candidate, to cover the tails as well
+    a.extend((0..247).map(|_| random_element(&mut rng)));
+    b.extend((0..247).map(|_| random_element(&mut rng)));
+    let mut out = vec![[0; 4]; a.len()];
+
+    (candidate.mul_batch)(&a, &b, &mut out);
+
+    a.iter().zip(&b).zip(&out).all(|((a, b), out)| {
+        less_than(*out, Bn254Fr::OUTPUT_MAX) && reduce::<Bn254Fr>(*out) == mont_mul(*a, *b, P)
+    })
+}
+
+/// Median time of `RUNS` runs after a warm up run
+fn measure(mul_batch: MulBatch, a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) -> Duration {
+    mul_batch(a, b, out);
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            mul_batch(std::hint::black_box(a), std::hint::black_box(b), out);
+            std::hint::black_box(&mut *out);
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+/// Below 2^253 < P
+fn random_element(rng: &mut StdRng) -> [u64; 4] {
+    let mut a: [u64; 4] = rng.random();
+    a[3] >>= 3;
+    a
+}
+
+fn sub_one(mut a: [u64; 4]) -> [u64; 4] {
+    for limb in &mut a {
+        let (diff, borrow) = limb.overflowing_sub(1);
+        *limb = diff;
+        if !borrow {
+            break;
+        }
+    }
+    a
+}
+
+fn less_than(a: [u64; 4], b: [u64; 4]) -> bool {
+    a.iter().rev().lt(b.iter().rev())
+}
+
+// -- [CANDIDATES] ---------------------------------------------------------------------------------
+
+fn acar_cios_opt(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    for ((a, b), out) in a.iter().zip(b).zip(out) {
+        let t = acar::cios_opt(*a, *b, P, NP0);
+        *out = [t[0], t[1], t[2], t[3]];
+    }
+}
+
+/// Four elements on the vector unit and two on the scalar unit per call
+fn emmart_fios_opt_sub_simd_sat_seq(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    let guard = RoundToZeroGuard::new();
+    for ((a, b), out) in a.chunks(6).zip(b.chunks(6)).zip(out.chunks_mut(6)) {
+        let a: [[u64; 4]; 6] = std::array::from_fn(|i| a[i.min(a.len() - 1)]);
+        let b: [[u64; 4]; 6] = std::array::from_fn(|i| b[i.min(b.len() - 1)]);
+        let (v, s0, s1) = emmart::fios_opt_sub_simd_sat_seq(
+            u256_to_u260_shl2(a[0]),
+            u256_to_u260_shl2(b[0]),
+            u256_to_u260_shl2(a[1]),
+            u256_to_u260_shl2(b[1]),
+            u256_to_u260_shl2(a[2]),
+            u256_to_u260_shl2(b[2]),
+            u256_to_u260_shl2(a[3]),
+            u256_to_u260_shl2(b[3]),
+            a[4],
+            b[4],
+            a[5],
+            b[5],
+            U52_P,
+            U52_NP0,
+        );
+        // The lower 256 bits are in the first five limbs
+        let v = v.map(|l| u260_to_u256([l[0], l[1], l[2], l[3], l[4]]));
+        let results = [
+            v[0],
+            v[1],
+            v[2],
+            v[3],
+            [s0[0], s0[1], s0[2], s0[3]],
+            [s1[0], s1[1], s1[2], s1[3]],
+        ];
+        out.copy_from_slice(&results[..out.len()]);
+    }
+    drop(guard);
+}
+
+fn domb_parallel_sub_simd_r256(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    for ((a, b), out) in a.chunks(2).zip(b.chunks(2)).zip(out.chunks_mut(2)) {
+        let results =
+            domb::parallel_sub_simd_r256([a[0], *a.last().unwrap()], [b[0], *b.last().unwrap()]);
+        out.copy_from_slice(&results[..out.len()]);
+    }
+}
+
+fn yuval_parallel(a: &[[u64; 4]], b: &[[u64; 4]], out: &mut [[u64; 4]]) {
+    for ((a, b), out) in a.iter().zip(b).zip(out) {
+        *out = yuval::parallel(*a, *b);
+    }
+}
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_are_correct() {
+        for candidate in &CANDIDATES {
+            assert!(validate(candidate), "{} is incorrect", candidate.name);
+        }
+    }
+
+    #[test]
+    fn mul_batch_matches_block_multiplier() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let a: Vec<[u64; 4]> = (0..100).map(|_| random_element(&mut rng)).collect();
+        let b: Vec<[u64; 4]> = (0..100).map(|_| random_element(&mut rng)).collect();
+        let mut out = vec![[0; 4]; 100];
+        mul_batch(&a, &b, &mut out);
+
+        for ((a, b), out) in a.iter().zip(&b).zip(&out) {
+            assert_eq!(reduce::<Bn254Fr>(*out), mont_mul(*a, *b, P));
+        }
+    }
+
+    #[test]
+    fn init_round_trips_the_cache() {
+        let cache = std::env::temp_dir().join(format!("tuner-{}", std::process::id()));
+        let selected = init(&cache).unwrap();
+        assert_eq!(fs::read_to_string(&cache).unwrap().trim(), selected.name);
+        fs::remove_file(cache).unwrap();
+    }
+}
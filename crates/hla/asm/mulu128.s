@@ -0,0 +1,9 @@
+.text
+.globl _mulu128
+.p2align 2
+_mulu128:
+    mul x2, x0, x1
+    umulh x0, x0, x1
+    mov x1, x0
+    mov x0, x2
+    ret
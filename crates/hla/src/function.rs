@@ -0,0 +1,753 @@
+//! Complete functions that can be called from Rust or C, generated from a function signature.
+
+use std::collections::HashSet;
+
+use crate::*;
+
+/// Types that can be passed to and returned from a generated function, every element takes one
+/// register
+pub trait AbiType {
+    /// The fresh registers that hold the value
+    type Regs;
+    /// The value in Rust, which [`rust_type`](AbiType::rust_type) names
+    type Value: Copy;
+    const ADDRESSING: Addressing;
+    const LEN: usize;
+    /// Arrays are passed like a #[repr(C)] struct of their elements, which differs from scalars
+    const COMPOSITE: bool;
+
+    /// The type in Rust source
+    fn rust_type() -> String;
+    fn fresh(asm: &mut Allocator) -> Self::Regs;
+    fn registers(regs: &Self::Regs) -> Vec<TypedSizedRegister<FreshRegister>>;
+}
+
+macro_rules! abi_scalar {
+    ($ty:ty, $value:ty, $addressing:ident, $rust:literal) => {
+        impl AbiType for $ty {
+            type Regs = Reg<$ty>;
+            type Value = $value;
+            const ADDRESSING: Addressing = Addressing::$addressing;
+            const LEN: usize = 1;
+            const COMPOSITE: bool = false;
+
+            fn rust_type() -> String {
+                $rust.to_string()
+            }
+
+            fn fresh(asm: &mut Allocator) -> Self::Regs {
+                asm.fresh()
+            }
+
+            fn registers(regs: &Self::Regs) -> Vec<TypedSizedRegister<FreshRegister>> {
+                vec![regs.to_typed_register()]
+            }
+        }
+    };
+}
+
+abi_scalar!(u64, u64, X, "u64");
+abi_scalar!(f64, f64, D, "f64");
+abi_scalar!(Simd<u64, 2>, core::simd::Simd<u64, 2>, V, "core::simd::Simd<u64, 2>");
+
+impl<T: AbiType<Regs = Reg<T>> + RegisterSource, const N: usize> AbiType for [T; N] {
+    type Regs = [Reg<T>; N];
+    type Value = [T::Value; N];
+    const ADDRESSING: Addressing = T::ADDRESSING;
+    const LEN: usize = N;
+    const COMPOSITE: bool = true;
+
+    fn rust_type() -> String {
+        format!("[{}; {N}]", T::rust_type())
+    }
+
+    fn fresh(asm: &mut Allocator) -> Self::Regs {
+        std::array::from_fn(|_| asm.fresh())
+    }
+
+    fn registers(regs: &Self::Regs) -> Vec<TypedSizedRegister<FreshRegister>> {
+        regs.iter().map(|reg| reg.to_typed_register()).collect()
+    }
+}
+
+/// Function pointer types `fn(A, B, ..) -> R` of which the parameters and the return value are
+/// [`AbiType`]s
+pub trait Signature {
+    /// A tuple with the fresh registers of every parameter
+    type Params;
+    type Ret: AbiType;
+    /// `extern "C" fn(A, B, ..) -> R` with the [`AbiType::Value`]s, the type of the function once
+    /// it's assembled
+    type Extern: Copy;
+
+    fn params(arguments: &mut Arguments) -> Self::Params;
+}
+
+macro_rules! signature {
+    ($($param:ident),+) => {
+        impl<$($param: AbiType,)+ R: AbiType> Signature for fn($($param),+) -> R {
+            type Params = ($($param::Regs,)+);
+            type Ret = R;
+            type Extern = extern "C" fn($($param::Value),+) -> R::Value;
+
+            fn params(arguments: &mut Arguments) -> Self::Params {
+                ($(arguments.param::<$param>(),)+)
+            }
+        }
+    };
+}
+
+signature!(A);
+signature!(A, B);
+signature!(A, B, C);
+signature!(A, B, C, D);
+signature!(A, B, C, D, E);
+signature!(A, B, C, D, E, F);
+signature!(A, B, C, D, E, F, G);
+signature!(A, B, C, D, E, F, G, H);
+
+// The register that holds the address of a result that is returned in memory
+const INDIRECT_RESULT: HardwareRegister = HardwareRegister(8);
+
+// Where AAPCS64 passes a value
+#[derive(Debug, PartialEq)]
+enum Location {
+    // One register per element
+    Registers(Vec<TypedSizedRegister<HardwareRegister>>),
+    // In memory at the address in an x register
+    Memory(HardwareRegister),
+}
+
+impl Location {
+    fn result(layout: Layout) -> Self {
+        match NextRegister::default().locate(layout) {
+            Location::Memory(_) => Location::Memory(INDIRECT_RESULT),
+            registers => registers,
+        }
+    }
+}
+
+// The next general purpose and SIMD/FP argument registers, NGRN and NSRN in AAPCS64
+#[derive(Debug, Default)]
+struct NextRegister {
+    x: u64,
+    v: u64,
+}
+
+impl NextRegister {
+    fn locate(&mut self, layout: Layout) -> Location {
+        let len = layout.len;
+        let in_registers = match layout.addressing {
+            // Composites of at most 16 bytes
+            Addressing::X => len * element_size(Addressing::X) <= 16,
+            // Homogeneous floating point and vector aggregates of at most 4 members
+            Addressing::V | Addressing::D | Addressing::Q => !layout.composite || len <= 4,
+        };
+        if in_registers {
+            Location::Registers(self.take(layout.addressing, len))
+        } else {
+            Location::Memory(self.take(Addressing::X, 1)[0].reg)
+        }
+    }
+
+    fn take(
+        &mut self,
+        addressing: Addressing,
+        len: u64,
+    ) -> Vec<TypedSizedRegister<HardwareRegister>> {
+        let next = match addressing {
+            Addressing::X => &mut self.x,
+            Addressing::V | Addressing::D | Addressing::Q => &mut self.v,
+        };
+        assert!(
+            *next + len <= 8,
+            "arguments that are passed on the stack are not supported"
+        );
+        let registers = (*next..*next + len)
+            .map(|reg| TypedSizedRegister {
+                reg: HardwareRegister(reg),
+                addressing,
+            })
+            .collect();
+        *next += len;
+        registers
+    }
+}
+
+fn element_size(addressing: Addressing) -> u64 {
+    match addressing {
+        Addressing::X | Addressing::D => 8,
+        Addressing::V | Addressing::Q => 16,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Layout {
+    pub(crate) addressing: Addressing,
+    pub(crate) len: u64,
+    pub(crate) composite: bool,
+}
+
+impl Layout {
+    fn of<T: AbiType>() -> Self {
+        Self {
+            addressing: T::ADDRESSING,
+            len: T::LEN as u64,
+            composite: T::COMPOSITE,
+        }
+    }
+}
+
+// A parameter or the return value
+#[derive(Debug)]
+pub(crate) struct Value {
+    pub(crate) layout: Layout,
+    pub(crate) rust_type: String,
+    pub(crate) registers: Vec<TypedSizedRegister<FreshRegister>>,
+}
+
+impl Value {
+    fn new<T: AbiType>(regs: &T::Regs) -> Self {
+        Self {
+            layout: Layout::of::<T>(),
+            rust_type: T::rust_type(),
+            registers: T::registers(regs),
+        }
+    }
+}
+
+/// The parameters of a function while their fresh registers are created
+pub struct Arguments<'a> {
+    asm: &'a mut Allocator,
+    params: Vec<Value>,
+}
+
+impl Arguments<'_> {
+    fn param<T: AbiType>(&mut self) -> T::Regs {
+        let regs = T::fresh(self.asm);
+        self.params.push(Value::new::<T>(&regs));
+        regs
+    }
+}
+
+// Run body on the fresh registers of the parameters of S, returns the parameters, the
+// instructions and the return value
+pub(crate) fn trace<S: Signature>(
+    asm: &mut Allocator,
+    body: impl FnOnce(&mut Allocator, S::Params) -> (Vec<Instruction>, <S::Ret as AbiType>::Regs),
+) -> (Vec<Value>, Vec<Instruction>, Value) {
+    let mut arguments = Arguments {
+        asm,
+        params: Vec::new(),
+    };
+    let params = S::params(&mut arguments);
+    let values = arguments.params;
+    let (instructions, ret) = body(asm, params);
+    (values, instructions, Value::new::<S::Ret>(&ret))
+}
+
+// The fresh registers that the instructions or the return value use
+pub(crate) fn used_registers(instructions: &[Instruction], ret: &Value) -> HashSet<FreshRegister> {
+    instructions
+        .iter()
+        .flat_map(|inst| inst.extract_registers())
+        .chain(ret.registers.iter().copied())
+        .map(|reg| *reg.as_fresh())
+        .collect()
+}
+
+// The hardware registers that hold the return value after the allocation. Values that are only
+// live out might have been spilled, those are reloaded at the end of body.
+pub(crate) fn output_registers(
+    mapping: &mut RegisterMapping,
+    register_bank: &mut RegisterBank,
+    body: &mut Vec<InstructionF<HardwareRegister>>,
+    ret: &Value,
+) -> Vec<TypedSizedRegister<HardwareRegister>> {
+    ret.registers
+        .iter()
+        .map(|fresh| TypedSizedRegister {
+            reg: final_register(mapping, register_bank, body, *fresh).reg,
+            addressing: fresh.addressing,
+        })
+        .collect()
+}
+
+// The hardware register of fresh at the end of body, reloaded if it was spilled
+fn final_register(
+    mapping: &mut RegisterMapping,
+    register_bank: &mut RegisterBank,
+    body: &mut Vec<InstructionF<HardwareRegister>>,
+    fresh: TypedSizedRegister<FreshRegister>,
+) -> TypedSizedRegister<HardwareRegister> {
+    match *mapping.index(*fresh.as_fresh()) {
+        RegisterState::Assigned(hw_reg) => hw_reg,
+        RegisterState::Spilled(addressing, slot) => {
+            let reg = TypedSizedRegister {
+                reg: register_bank
+                    .take(addressing)
+                    .expect("ran out of registers to reload the outputs"),
+                addressing,
+            };
+            body.push(stack_instruction("ldr", reg, slot));
+            *mapping.index_mut(*fresh.as_fresh()) = RegisterState::Assigned(reg);
+            reg
+        }
+        _ => panic!("{fresh:?} is returned but never assigned"),
+    }
+}
+
+// A ldr or str of element i of an array at the address in base
+fn memory_instruction<R>(
+    opcode: &str,
+    reg: TypedSizedRegister<R>,
+    base: TypedSizedRegister<R>,
+    i: usize,
+) -> InstructionF<R> {
+    InstructionF {
+        opcode: opcode.to_string(),
+        modifiers: Mod::Base(i as u64 * element_size(reg.addressing)),
+        dest: reg,
+        src: vec![base],
+    }
+}
+
+// A register to register move, the SIMD/FP registers are moved in full
+fn move_instruction(
+    src: TypedSizedRegister<HardwareRegister>,
+    dest: TypedSizedRegister<HardwareRegister>,
+) -> InstructionF<HardwareRegister> {
+    let (opcode, addressing) = match dest.addressing {
+        Addressing::X => ("mov", Addressing::X),
+        Addressing::V | Addressing::D | Addressing::Q => ("mov.16b", Addressing::V),
+    };
+    InstructionF {
+        opcode: opcode.to_string(),
+        dest: TypedSizedRegister {
+            reg: dest.reg,
+            addressing,
+        },
+        src: vec![TypedSizedRegister {
+            reg: src.reg,
+            addressing,
+        }],
+        modifiers: Mod::None,
+    }
+}
+
+fn aliases(
+    a: TypedSizedRegister<HardwareRegister>,
+    b: TypedSizedRegister<HardwareRegister>,
+) -> bool {
+    a.reg == b.reg && RegisterBank::same_pool(a.addressing, b.addressing)
+}
+
+// Sequence the (source, target) moves such that every target gets the value its source had
+// before any of the moves. A cycle is broken by moving through a free register.
+fn parallel_move(
+    register_bank: &mut RegisterBank,
+    mut moves: Vec<(
+        TypedSizedRegister<HardwareRegister>,
+        TypedSizedRegister<HardwareRegister>,
+    )>,
+) -> Vec<InstructionF<HardwareRegister>> {
+    moves.retain(|&(src, dest)| !aliases(src, dest));
+    let mut out = Vec::new();
+    while !moves.is_empty() {
+        // A move whose target isn't the source of another move
+        let ready = moves
+            .iter()
+            .position(|&(_, dest)| moves.iter().all(|&(src, _)| !aliases(src, dest)));
+        match ready {
+            Some(i) => {
+                let (src, dest) = moves.remove(i);
+                out.push(move_instruction(src, dest));
+            }
+            None => {
+                // Every target is also a source, so the moves form cycles
+                let (src, _) = moves[0];
+                let tmp = TypedSizedRegister {
+                    reg: register_bank
+                        .take(src.addressing)
+                        .expect("ran out of registers: a move cycle needs a free one"),
+                    addressing: src.addressing,
+                };
+                out.push(move_instruction(src, tmp));
+                moves[0].0 = tmp;
+            }
+        }
+    }
+    out
+}
+
+/// A generated function. Display gives the assembly source, which can be included with
+/// `global_asm!`.
+#[derive(Debug)]
+pub struct Function {
+    symbol: String,
+    platform: Platform,
+    // Saves of the callee-saved registers
+    pub(crate) prologue: Vec<InstructionF<HardwareRegister>>,
+    pub(crate) body: Vec<InstructionF<HardwareRegister>>,
+    pub(crate) epilogue: Vec<InstructionF<HardwareRegister>>,
+    // Spill slots, see RegisterMapping::frame_size
+    pub(crate) frame_size: u64,
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = &self.symbol;
+        writeln!(f, ".text")?;
+        writeln!(f, ".globl {symbol}")?;
+        writeln!(f, ".p2align 2")?;
+        if self.platform == Platform::Linux {
+            writeln!(f, ".type {symbol}, %function")?;
+        }
+        writeln!(f, "{symbol}:")?;
+
+        let frame_size = self.frame_size;
+        for inst in &self.prologue {
+            writeln!(f, "    {}", inst.format_instruction())?;
+        }
+        if frame_size > 0 {
+            writeln!(f, "    sub sp, sp, #{frame_size}")?;
+        }
+        for inst in &self.body {
+            writeln!(f, "    {}", inst.format_instruction())?;
+        }
+        if frame_size > 0 {
+            writeln!(f, "    add sp, sp, #{frame_size}")?;
+        }
+        for inst in &self.epilogue {
+            writeln!(f, "    {}", inst.format_instruction())?;
+        }
+        writeln!(f, "    ret")?;
+
+        if self.platform == Platform::Linux {
+            writeln!(f, ".size {symbol}, .-{symbol}")?;
+        }
+        Ok(())
+    }
+}
+
+// Insert every load right before the first instruction that uses its register, such that the
+// element doesn't take a register before then
+fn insert_loads(instructions: Vec<Instruction>, loads: Vec<Instruction>) -> Vec<Instruction> {
+    let first_use = |load: &Instruction| {
+        instructions
+            .iter()
+            .position(|inst| {
+                inst.extract_registers()
+                    .iter()
+                    .any(|reg| reg.as_fresh() == load.dest.as_fresh())
+            })
+            .unwrap_or(instructions.len())
+    };
+    let mut loads: Vec<_> = loads
+        .into_iter()
+        .map(|load| (first_use(&load), load))
+        .collect();
+    loads.sort_by_key(|(position, _)| *position);
+
+    let mut loads = loads.into_iter().peekable();
+    let mut out = Vec::new();
+    for (i, inst) in instructions.into_iter().enumerate() {
+        while let Some((_, load)) = loads.next_if(|(position, _)| *position == i) {
+            out.push(load);
+        }
+        out.push(inst);
+    }
+    out.extend(loads.map(|(_, load)| load));
+    out
+}
+
+/// Generate the function `name` with signature `S`, such as `fn([u64; 4], u64) -> [u64; 5]`.
+///
+/// `body` gets the fresh registers of the parameters and returns the instructions together with
+/// the fresh registers of the return value. The parameters and the return value are passed as
+/// AAPCS64 passes them to and from an `extern "C"` function, with arrays laid out like a
+/// #[repr(C)] struct of their elements:
+/// - scalars and arrays of at most 16 bytes of u64 in x0-x7
+/// - f64, `Simd<u64, 2>` and arrays of at most 4 of them in v0-v7
+/// - larger arrays in memory. The address of a parameter is passed in an x register and that of
+///   the return value in x8. Every element is loaded right before its first use, the return
+///   value is stored at the end.
+///
+/// Callee-saved registers are used when the others run out and are saved in the prologue. The
+/// vector instructions use the Apple assembler syntax.
+pub fn function<S: Signature>(
+    name: &str,
+    platform: Platform,
+    body: impl FnOnce(&mut Allocator, S::Params) -> (Vec<Instruction>, <S::Ret as AbiType>::Regs),
+) -> Function {
+    let mut asm = Allocator::new();
+    let (params, instructions, ret) = trace::<S>(&mut asm, body);
+    let used = used_registers(&instructions, &ret);
+
+    // Parameters that aren't used don't take a register, those in memory aren't loaded
+    let mut mapping = RegisterMapping::new();
+    let mut register_bank = RegisterBank::with_abi(platform, true);
+    let mut next = NextRegister::default();
+    let mut loads = Vec::new();
+    for param in params {
+        let location = next.locate(param.layout);
+        let mut elements = param
+            .registers
+            .into_iter()
+            .enumerate()
+            .filter(|(_, fresh)| used.contains(fresh.as_fresh()))
+            .peekable();
+        match location {
+            Location::Registers(hw_regs) => {
+                for (i, fresh) in elements {
+                    assign_input(&mut mapping, &mut register_bank, fresh, hw_regs[i].reg);
+                }
+            }
+            Location::Memory(address) if elements.peek().is_some() => {
+                let address: Reg<u64> =
+                    input(&mut asm, &mut mapping, &mut register_bank, address.0);
+                for (i, fresh) in elements {
+                    loads.push(memory_instruction(
+                        "ldr",
+                        fresh,
+                        address.to_typed_register(),
+                        i,
+                    ));
+                }
+            }
+            Location::Memory(_) => {}
+        }
+    }
+
+    let mut seen = Seen::new();
+    let result = Location::result(ret.layout);
+    let result_address = match result {
+        Location::Memory(address) => {
+            // The address has to survive until the result is stored, it might be spilled though
+            let address: Reg<u64> = input(&mut asm, &mut mapping, &mut register_bank, address.0);
+            seen.output_interface(&address);
+            Some(address.to_typed_register())
+        }
+        Location::Registers(_) => None,
+    };
+
+    let instructions = insert_loads(instructions, loads);
+
+    ret.registers.iter().for_each(|reg| {
+        seen.seen(*reg.as_fresh());
+    });
+    let releases = liveness_analysis(&mut seen, &instructions);
+    let mut body =
+        hardware_register_allocation(&mut mapping, &mut register_bank, instructions, releases);
+
+    let outputs = output_registers(&mut mapping, &mut register_bank, &mut body, &ret);
+    match result {
+        Location::Registers(targets) => {
+            body.extend(parallel_move(
+                &mut register_bank,
+                outputs.into_iter().zip(targets).collect(),
+            ));
+        }
+        Location::Memory(_) => {
+            let address = final_register(
+                &mut mapping,
+                &mut register_bank,
+                &mut body,
+                result_address.expect("the result address is allocated"),
+            );
+            body.extend(
+                outputs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, reg)| memory_instruction("str", reg, address, i)),
+            );
+        }
+    }
+
+    let frame_size = mapping.frame_size();
+    assert!(
+        frame_size < 4096,
+        "the spill slots don't fit in the immediate of sub"
+    );
+    Function {
+        symbol: platform.symbol(name),
+        platform,
+        prologue: register_bank.prologue(),
+        body,
+        epilogue: register_bank.epilogue(),
+        frame_size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{execute, wide_kernel};
+    use std::collections::{BTreeMap, HashMap};
+
+    fn gen_mulu128(c: &[Reg<u64>; 2], a: &Reg<u64>, b: &Reg<u64>) -> Vec<Instruction> {
+        vec![mul(&c[0], a, b), umulh(&c[1], a, b)]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    fn mulu128(platform: Platform) -> Function {
+        function::<fn(u64, u64) -> [u64; 2]>("mulu128", platform, |asm, (a, b)| {
+            let c = std::array::from_fn(|_| asm.fresh());
+            (gen_mulu128(&c, &a, &b), c)
+        })
+    }
+
+    fn x_registers(values: &[u64]) -> BTreeMap<HardwareRegister, u64> {
+        (0..)
+            .map(HardwareRegister)
+            .zip(values.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn emit_function() {
+        assert_eq!(
+            mulu128(Platform::Apple).to_string(),
+            "\
+.text
+.globl _mulu128
+.p2align 2
+_mulu128:
+    mul x2, x0, x1
+    umulh x0, x0, x1
+    mov x1, x0
+    mov x0, x2
+    ret
+"
+        );
+
+        let linux = mulu128(Platform::Linux).to_string();
+        assert!(linux.contains(".globl mulu128\n"));
+        assert!(linux.contains(".type mulu128, %function\nmulu128:\n"));
+        assert!(linux.ends_with("    ret\n.size mulu128, .-mulu128\n"));
+    }
+
+    #[test]
+    fn return_in_registers() {
+        let f = mulu128(Platform::Apple);
+        let regs = execute(&f.body, x_registers(&[u64::MAX, 3]), &mut HashMap::new());
+        let product = u64::MAX as u128 * 3;
+        assert_eq!(regs[&HardwareRegister(0)], product as u64);
+        assert_eq!(regs[&HardwareRegister(1)], (product >> 64) as u64);
+    }
+
+    #[test]
+    fn swap_through_free_register() {
+        let f = function::<fn(u64, u64) -> [u64; 2]>("swap", Platform::Apple, |_, (a, b)| {
+            (vec![], [b, a])
+        });
+        assert_eq!(f.body.len(), 3);
+        let regs = execute(&f.body, x_registers(&[5, 7]), &mut HashMap::new());
+        assert_eq!(regs[&HardwareRegister(0)], 7);
+        assert_eq!(regs[&HardwareRegister(1)], 5);
+    }
+
+    #[test]
+    fn pass_arrays_in_memory() {
+        // The last element of a isn't used and b is returned as is
+        let f =
+            function::<fn([u64; 4], u64) -> [u64; 4]>("scale", Platform::Apple, |asm, (a, b)| {
+                let c: [Reg<u64>; 3] = std::array::from_fn(|_| asm.fresh());
+                let instructions = (0..3).flat_map(|i| mul(&c[i], &a[i], &b)).collect();
+                let [c0, c1, c2] = c;
+                (instructions, [c0, c1, c2, b])
+            });
+        // Every element is loaded right before it's multiplied
+        let opcodes: Vec<_> = f.body.iter().map(|i| i.opcode.as_str()).collect();
+        assert_eq!(opcodes[..6], ["ldr", "mul", "ldr", "mul", "ldr", "mul"]);
+        assert_eq!(opcodes.iter().filter(|&&op| op == "ldr").count(), 3);
+
+        let (a, result) = (0x1000, 0x2000);
+        let mut memory: HashMap<u64, u64> = (0..4).map(|i| (a + 8 * i, i + 2)).collect();
+        let mut regs = x_registers(&[a, 10]);
+        regs.insert(INDIRECT_RESULT, result);
+        execute(&f.body, regs, &mut memory);
+        let c: Vec<_> = (0..4).map(|i| memory[&(result + 8 * i)]).collect();
+        assert_eq!(c, [20, 30, 40, 10]);
+    }
+
+    #[test]
+    fn spill_the_result_address() {
+        // The result address and the product are only live out while the wide kernel spills
+        let f = function::<fn(u64, u64) -> [u64; 4]>("wide", Platform::Apple, |asm, (a, b)| {
+            let c = [asm.fresh(), asm.fresh()];
+            let mut instructions = gen_mulu128(&c, &a, &b);
+            let (wide, sum, _) = wide_kernel(asm);
+            instructions.extend(wide);
+            let [c0, c1] = c;
+            (instructions, [c0, c1, sum, b])
+        });
+        assert!(f.frame_size > 0);
+
+        let result = 0x2000;
+        let mut memory = HashMap::new();
+        let mut regs = x_registers(&[u64::MAX, 3]);
+        regs.insert(INDIRECT_RESULT, result);
+        execute(&f.body, regs, &mut memory);
+        let (_, _, expected) = wide_kernel(&mut Allocator::new());
+        let c: Vec<_> = (0..4).map(|i| memory[&(result + 8 * i)]).collect();
+        assert_eq!(c, [u64::MAX - 2, 2, expected, 3]);
+    }
+
+    #[test]
+    fn locate_arguments() {
+        let x = |reg| u64::to_typed_register(HardwareRegister(reg));
+        let d = |reg| f64::to_typed_register(HardwareRegister(reg));
+        let v = |reg| Simd::<u64, 2>::to_typed_register(HardwareRegister(reg));
+
+        let mut next = NextRegister::default();
+        assert_eq!(
+            next.locate(Layout::of::<f64>()),
+            Location::Registers(vec![d(0)])
+        );
+        assert_eq!(
+            next.locate(Layout::of::<[f64; 4]>()),
+            Location::Registers((1..5).map(d).collect())
+        );
+        assert_eq!(
+            next.locate(Layout::of::<Simd<u64, 2>>()),
+            Location::Registers(vec![v(5)])
+        );
+        assert_eq!(
+            next.locate(Layout::of::<[u64; 2]>()),
+            Location::Registers(vec![x(0), x(1)])
+        );
+        assert_eq!(
+            next.locate(Layout::of::<[u64; 3]>()),
+            Location::Memory(HardwareRegister(2))
+        );
+        assert_eq!(
+            next.locate(Layout::of::<[Simd<u64, 2>; 5]>()),
+            Location::Memory(HardwareRegister(3))
+        );
+        assert_eq!(
+            next.locate(Layout::of::<u64>()),
+            Location::Registers(vec![x(4)])
+        );
+
+        assert_eq!(
+            Location::result(Layout::of::<[u64; 4]>()),
+            Location::Memory(INDIRECT_RESULT)
+        );
+        assert_eq!(
+            Location::result(Layout::of::<[u64; 2]>()),
+            Location::Registers(vec![x(0), x(1)])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "passed on the stack")]
+    fn too_many_arguments() {
+        let mut next = NextRegister::default();
+        next.locate(Layout::of::<[Simd<u64, 2>; 4]>());
+        next.locate(Layout::of::<[f64; 4]>());
+        next.locate(Layout::of::<f64>());
+    }
+}
@@ -0,0 +1,354 @@
+//! Rust functions with the instructions in an `asm!` block. The registers within the block are
+//! allocated here, those at its interface by LLVM.
+
+use std::collections::BTreeMap;
+
+use crate::*;
+
+/// A generated Rust function. Display gives the source, which can be included with `include!`.
+/// Vector parameters or return values need `#![feature(portable_simd)]`.
+#[derive(Debug)]
+pub struct InlineFunction {
+    name: String,
+    // Name and type of every parameter
+    params: Vec<(String, String)>,
+    ret: Value,
+    template: Vec<String>,
+    operands: Vec<String>,
+    options: &'static str,
+}
+
+impl std::fmt::Display for InlineFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{name}: {ty}"))
+            .collect();
+        let ret = &self.ret.rust_type;
+
+        writeln!(f, "#[inline]")?;
+        writeln!(f, "pub fn {}({}) -> {ret} {{", self.name, params.join(", "))?;
+        // Arrays are outputs element by element so they have to be initialised
+        if self.ret.layout.composite {
+            writeln!(f, "    let mut out: {ret} = Default::default();")?;
+        } else {
+            writeln!(f, "    let out: {ret};")?;
+        }
+        writeln!(f, "    unsafe {{")?;
+        writeln!(f, "        core::arch::asm!(")?;
+        for line in &self.template {
+            writeln!(f, "            \"{line}\",")?;
+        }
+        for operand in &self.operands {
+            writeln!(f, "            {operand},")?;
+        }
+        writeln!(f, "            {},", self.options)?;
+        writeln!(f, "        );")?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "    out")?;
+        writeln!(f, "}}")
+    }
+}
+
+// Name of the asm! operand of a slot
+fn operand_name(reg: TypedSizedRegister<HardwareRegister>) -> String {
+    match reg.addressing {
+        Addressing::X => format!("r{}", reg.reg),
+        Addressing::V | Addressing::D | Addressing::Q => format!("v{}", reg.reg),
+    }
+}
+
+// Placeholder of an operand in the template, vector registers need a modifier for their name
+fn placeholder(reg: TypedSizedRegister<HardwareRegister>) -> String {
+    let name = operand_name(reg);
+    match reg.addressing {
+        Addressing::X => format!("{{{name}}}"),
+        Addressing::V => format!("{{{name}:v}}"),
+        Addressing::D => format!("{{{name}:d}}"),
+        Addressing::Q => format!("{{{name}:q}}"),
+    }
+}
+
+// Element i of the parameter or return value name
+fn element(name: &str, layout: Layout, i: usize) -> String {
+    if layout.composite {
+        format!("{name}[{i}]")
+    } else {
+        name.to_string()
+    }
+}
+
+// The Rust values an operand slot is bound to
+#[derive(Debug, Default)]
+struct Binding {
+    input: Option<String>,
+    output: Option<String>,
+    written: bool,
+}
+
+impl Binding {
+    fn operand(&self, class: &str) -> String {
+        match (&self.input, &self.output) {
+            (Some(input), Some(output)) => format!("inout({class}) {input} => {output}"),
+            (Some(input), None) if self.written => format!("inout({class}) {input} => _"),
+            (Some(input), None) => format!("in({class}) {input}"),
+            (None, Some(output)) => format!("out({class}) {output}"),
+            (None, None) => format!("out({class}) _"),
+        }
+    }
+}
+
+/// Generate the Rust function `name` with signature `S` and parameters named `param_names`.
+///
+/// `body` is called as for [`function`]. Its instructions are allocated to operand slots instead
+/// of hardware registers, every slot becomes a named `in`, `out` or `inout` operand of the
+/// `asm!` block and LLVM assigns them to registers when the function is inlined. Parameters and
+/// the return value are bound element by element, the return value is named `out`. Spills lower
+/// sp within the block.
+pub fn inline_function<S: Signature>(
+    name: &str,
+    param_names: &[&str],
+    body: impl FnOnce(&mut Allocator, S::Params) -> (Vec<Instruction>, <S::Ret as AbiType>::Regs),
+) -> InlineFunction {
+    let mut asm = Allocator::new();
+    let (params, instructions, ret) = trace::<S>(&mut asm, body);
+    assert_eq!(
+        params.len(),
+        param_names.len(),
+        "every parameter needs a name"
+    );
+    assert!(
+        !param_names.contains(&"out"),
+        "out is the name of the return value"
+    );
+
+    let used = used_registers(&instructions, &ret);
+
+    // Elements that aren't used aren't bound
+    let mut mapping = RegisterMapping::new();
+    let mut register_bank = RegisterBank::operands();
+    // Ordered such that the operands are listed by slot, x before v
+    let mut bindings: BTreeMap<(bool, HardwareRegister), Binding> = BTreeMap::new();
+    let slot =
+        |reg: TypedSizedRegister<HardwareRegister>| (reg.addressing != Addressing::X, reg.reg);
+
+    for (param, name) in params.iter().zip(param_names) {
+        for (i, fresh) in param.registers.iter().enumerate() {
+            if !used.contains(fresh.as_fresh()) {
+                continue;
+            }
+            let reg = TypedSizedRegister {
+                reg: register_bank
+                    .take(fresh.addressing)
+                    .expect("ran out of operands for the parameters"),
+                addressing: fresh.addressing,
+            };
+            *mapping.index_mut(*fresh.as_fresh()) = RegisterState::Assigned(reg);
+            bindings.entry(slot(reg)).or_default().input = Some(element(name, param.layout, i));
+        }
+    }
+
+    let mut seen = Seen::new();
+    ret.registers.iter().for_each(|reg| {
+        seen.seen(*reg.as_fresh());
+    });
+    let releases = liveness_analysis(&mut seen, &instructions);
+    let mut body =
+        hardware_register_allocation(&mut mapping, &mut register_bank, instructions, releases);
+
+    let outputs = output_registers(&mut mapping, &mut register_bank, &mut body, &ret);
+    for (i, reg) in outputs.into_iter().enumerate() {
+        bindings.entry(slot(reg)).or_default().output = Some(element("out", ret.layout, i));
+    }
+    // Every operand has to appear in the template, those of values that are only passed through
+    // don't appear in an instruction
+    let mut unused: Vec<_> = bindings.keys().copied().collect();
+    for inst in &body {
+        for reg in inst.extract_registers() {
+            unused.retain(|&unused| unused != slot(reg));
+            bindings.entry(slot(reg)).or_default();
+        }
+        // Stores only read their register
+        if !inst.opcode.starts_with("st") {
+            bindings.entry(slot(inst.dest)).or_default().written = true;
+        }
+    }
+
+    let mut template: Vec<_> = body
+        .iter()
+        .map(|inst| inst.format_with(placeholder))
+        .collect();
+    if !unused.is_empty() {
+        let placeholders: Vec<_> = unused
+            .into_iter()
+            .map(|(vector, reg)| {
+                let addressing = if vector { Addressing::V } else { Addressing::X };
+                placeholder(TypedSizedRegister { reg, addressing })
+            })
+            .collect();
+        template.push(format!("/* {} */", placeholders.join(" ")));
+    }
+    let frame_size = mapping.frame_size();
+    let options = if frame_size > 0 {
+        template.insert(0, format!("sub sp, sp, #{frame_size}"));
+        template.push(format!("add sp, sp, #{frame_size}"));
+        "options(pure, nomem)"
+    } else {
+        "options(pure, nomem, nostack)"
+    };
+
+    let operands = bindings
+        .iter()
+        .map(|(&(vector, reg), binding)| {
+            let (addressing, class) = if vector {
+                (Addressing::V, "vreg")
+            } else {
+                (Addressing::X, "reg")
+            };
+            let name = operand_name(TypedSizedRegister { reg, addressing });
+            format!("{name} = {}", binding.operand(class))
+        })
+        .collect();
+
+    InlineFunction {
+        name: name.to_string(),
+        params: params
+            .into_iter()
+            .zip(param_names)
+            .map(|(param, name)| {
+                let unused = param.registers.iter().all(|r| !used.contains(r.as_fresh()));
+                let name = if unused {
+                    format!("_{name}")
+                } else {
+                    name.to_string()
+                };
+                (name, param.rust_type)
+            })
+            .collect(),
+        ret,
+        template,
+        operands,
+        options,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::wide_kernel;
+
+    #[test]
+    fn inline_mulu128() {
+        let f =
+            inline_function::<fn(u64, u64) -> [u64; 2]>("mulu128", &["a", "b"], |asm, (a, b)| {
+                let c: [Reg<u64>; 2] = std::array::from_fn(|_| asm.fresh());
+                let instructions = [mul(&c[0], &a, &b), umulh(&c[1], &a, &b)];
+                (instructions.into_iter().flatten().collect(), c)
+            });
+        assert_eq!(
+            f.to_string(),
+            r#"#[inline]
+pub fn mulu128(a: u64, b: u64) -> [u64; 2] {
+    let mut out: [u64; 2] = Default::default();
+    unsafe {
+        core::arch::asm!(
+            "mul {r2}, {r0}, {r1}",
+            "umulh {r0}, {r0}, {r1}",
+            r0 = inout(reg) a => out[1],
+            r1 = in(reg) b,
+            r2 = out(reg) out[0],
+            options(pure, nomem, nostack),
+        );
+    }
+    out
+}
+"#
+        );
+    }
+
+    #[test]
+    fn inline_pass_through() {
+        let f = inline_function::<fn(u64, u64) -> [u64; 2]>("swap", &["a", "b"], |_, (a, b)| {
+            (vec![], [b, a])
+        });
+        assert_eq!(
+            f.to_string(),
+            r#"#[inline]
+pub fn swap(a: u64, b: u64) -> [u64; 2] {
+    let mut out: [u64; 2] = Default::default();
+    unsafe {
+        core::arch::asm!(
+            "/* {r0} {r1} */",
+            r0 = inout(reg) a => out[1],
+            r1 = inout(reg) b => out[0],
+            options(pure, nomem, nostack),
+        );
+    }
+    out
+}
+"#
+        );
+
+        // A vector that is returned as is, next to an instruction
+        let f = inline_function::<fn(u64, Simd<u64, 2>) -> [Simd<u64, 2>; 2]>(
+            "pass",
+            &["a", "s"],
+            |asm, (a, s)| {
+                let t = asm.fresh();
+                (dup2d(&t, &a), [t, s])
+            },
+        );
+        assert_eq!(f.template, ["dup.2d {v1:v}, {r0}", "/* {v0:v} */"]);
+    }
+
+    #[test]
+    fn inline_vector_registers() {
+        let f = inline_function::<fn(Simd<u64, 2>, u64) -> Simd<u64, 2>>(
+            "convert",
+            &["s", "c"],
+            |asm, (s, c)| {
+                let t = asm.fresh();
+                let instructions = [ucvtf2d(&s, &s), dup2d(&t, &c), fmla2d(&t, &s, &s, 1)];
+                (instructions.into_iter().flatten().collect(), t)
+            },
+        );
+        assert_eq!(
+            f.template,
+            [
+                "ucvtf.2d {v0:v}, {v0:v}",
+                "dup.2d {v1:v}, {r0}",
+                "fmla.2d {v1:v}, {v0:v}, {v0:v}[1]"
+            ]
+        );
+        assert_eq!(
+            f.operands,
+            [
+                "r0 = in(reg) c",
+                "v0 = inout(vreg) s => _",
+                "v1 = out(vreg) out"
+            ]
+        );
+        assert!(
+            f.to_string()
+                .contains("pub fn convert(s: core::simd::Simd<u64, 2>, c: u64) -> core::simd::Simd<u64, 2> {\n    let out: core::simd::Simd<u64, 2>;\n")
+        );
+    }
+
+    #[test]
+    fn inline_spills_on_the_stack() {
+        let f = inline_function::<fn(u64) -> u64>("wide", &["a"], |asm, (_a,)| {
+            let (instructions, sum, _) = wide_kernel(asm);
+            (instructions, sum)
+        });
+        let frame_size = f.template[0].strip_prefix("sub sp, sp, #").unwrap();
+        assert_eq!(
+            f.template.last().unwrap(),
+            &format!("add sp, sp, #{frame_size}")
+        );
+        assert!(f.template.iter().any(|line| line.starts_with("str {r")));
+        assert_eq!(f.operands.len(), 27);
+        assert!(f.to_string().contains("pub fn wide(_a: u64) -> u64 {"));
+        assert_eq!(f.options, "options(pure, nomem)");
+    }
+}
@@ -0,0 +1,286 @@
+//! Assemble generated functions into executable memory with dynasm, such that they can be called
+//! without writing files or running the assembler toolchain.
+
+use std::marker::PhantomData;
+
+use dynasmrt::{AssemblyOffset, DynasmApi, ExecutableBuffer, aarch64::Assembler, dynasm};
+
+use crate::*;
+
+// dynasm assembles for the host unless told otherwise
+macro_rules! aarch64 {
+    ($ops:ident $($t:tt)*) => {
+        dynasm!($ops ; .arch aarch64 $($t)*)
+    };
+}
+
+// Load a 64 bit immediate with a movz of its lowest nonzero 16 bit chunk and a movk of the others
+fn mov_imm(ops: &mut Assembler, dest: u32, imm: u64) {
+    let mut chunks = (0..4)
+        .map(|i| (i, (imm >> (16 * i)) as u32 & 0xffff))
+        .filter(|&(_, chunk)| chunk != 0);
+    let (i, chunk) = chunks.next().unwrap_or((0, 0));
+    let shift = 16 * i;
+    aarch64!(ops ; movz X(dest), chunk, lsl shift);
+    for (i, chunk) in chunks {
+        let shift = 16 * i;
+        aarch64!(ops ; movk X(dest), chunk, lsl shift);
+    }
+}
+
+/// Append the machine code of an instruction to `ops`. Panics on instructions that hla doesn't
+/// generate.
+pub fn lower(ops: &mut Assembler, inst: &InstructionF<HardwareRegister>) {
+    let d = inst.dest.reg.0 as u32;
+    let s: Vec<u32> = inst.src.iter().map(|reg| reg.reg.0 as u32).collect();
+    let unsupported = || panic!("{} can't be lowered", inst.format_instruction());
+
+    match (inst.opcode.as_str(), &inst.modifiers, inst.dest.addressing) {
+        ("mov", Mod::Imm(imm), Addressing::X) => mov_imm(ops, d, *imm),
+        ("mov", Mod::None, Addressing::X) => aarch64!(ops ; mov X(d), X(s[0])),
+        ("mul", Mod::None, _) => aarch64!(ops ; mul X(d), X(s[0]), X(s[1])),
+        ("umulh", Mod::None, _) => aarch64!(ops ; umulh X(d), X(s[0]), X(s[1])),
+        ("adds", Mod::None, _) => aarch64!(ops ; adds X(d), X(s[0]), X(s[1])),
+        ("adcs", Mod::None, _) => aarch64!(ops ; adcs X(d), X(s[0]), X(s[1])),
+        ("cinc", Mod::Cond(cond), _) => match cond.as_str() {
+            "hs" | "cs" => aarch64!(ops ; cinc X(d), X(s[0]), hs),
+            "lo" | "cc" => aarch64!(ops ; cinc X(d), X(s[0]), lo),
+            "eq" => aarch64!(ops ; cinc X(d), X(s[0]), eq),
+            "ne" => aarch64!(ops ; cinc X(d), X(s[0]), ne),
+            _ => unsupported(),
+        },
+        ("mov.16b", Mod::None, _) => aarch64!(ops ; mov V(d).B16, V(s[0]).B16),
+        ("ucvtf.2d", Mod::None, _) => aarch64!(ops ; ucvtf V(d).D2, V(s[0]).D2),
+        ("dup.2d", Mod::None, _) => aarch64!(ops ; dup V(d).D2, X(s[0])),
+        ("ucvtf", Mod::None, Addressing::D) => aarch64!(ops ; ucvtf D(d), X(s[0])),
+        ("fmla.2d", Mod::Idx(i), _) => {
+            let i = *i as u32;
+            aarch64!(ops ; fmla V(d).D2, V(s[0]).D2, V(s[1]).D[i])
+        }
+
+        // Spill slots
+        ("str", Mod::Offset(offset), addressing) => {
+            let offset = *offset as u32;
+            match addressing {
+                Addressing::X => aarch64!(ops ; str X(d), [sp, offset]),
+                Addressing::Q => aarch64!(ops ; str Q(d), [sp, offset]),
+                _ => unsupported(),
+            }
+        }
+        ("ldr", Mod::Offset(offset), addressing) => {
+            let offset = *offset as u32;
+            match addressing {
+                Addressing::X => aarch64!(ops ; ldr X(d), [sp, offset]),
+                Addressing::Q => aarch64!(ops ; ldr Q(d), [sp, offset]),
+                _ => unsupported(),
+            }
+        }
+
+        // Parameters and return values in memory
+        ("str", Mod::Base(offset), addressing) => {
+            let offset = *offset as u32;
+            match addressing {
+                Addressing::X => aarch64!(ops ; str X(d), [X(s[0]), offset]),
+                Addressing::D => aarch64!(ops ; str D(d), [X(s[0]), offset]),
+                Addressing::V | Addressing::Q => aarch64!(ops ; str Q(d), [X(s[0]), offset]),
+            }
+        }
+        ("ldr", Mod::Base(offset), addressing) => {
+            let offset = *offset as u32;
+            match addressing {
+                Addressing::X => aarch64!(ops ; ldr X(d), [X(s[0]), offset]),
+                Addressing::D => aarch64!(ops ; ldr D(d), [X(s[0]), offset]),
+                Addressing::V | Addressing::Q => aarch64!(ops ; ldr Q(d), [X(s[0]), offset]),
+            }
+        }
+
+        // Saves of the callee-saved registers
+        (opcode, Mod::PreIndex(offset), addressing) => {
+            let offset = -(*offset as i32);
+            match (opcode, addressing) {
+                ("str", Addressing::X) => aarch64!(ops ; str X(d), [sp, offset]!),
+                ("str", Addressing::D) => aarch64!(ops ; str D(d), [sp, offset]!),
+                ("stp", Addressing::X) => aarch64!(ops ; stp X(d), X(s[0]), [sp, offset]!),
+                ("stp", Addressing::D) => aarch64!(ops ; stp D(d), D(s[0]), [sp, offset]!),
+                _ => unsupported(),
+            }
+        }
+        (opcode, Mod::PostIndex(offset), addressing) => {
+            let offset = *offset as i32;
+            match (opcode, addressing) {
+                ("ldr", Addressing::X) => aarch64!(ops ; ldr X(d), [sp], offset),
+                ("ldr", Addressing::D) => aarch64!(ops ; ldr D(d), [sp], offset),
+                ("ldp", Addressing::X) => aarch64!(ops ; ldp X(d), X(s[0]), [sp], offset),
+                ("ldp", Addressing::D) => aarch64!(ops ; ldp D(d), D(s[0]), [sp], offset),
+                _ => unsupported(),
+            }
+        }
+        _ => unsupported(),
+    }
+}
+
+impl Function {
+    /// Append the machine code of the function to `ops`, returns where it starts
+    pub fn assemble(&self, ops: &mut Assembler) -> AssemblyOffset {
+        let start = ops.offset();
+        let frame_size = self.frame_size as u32;
+        self.prologue.iter().for_each(|inst| lower(ops, inst));
+        if frame_size > 0 {
+            aarch64!(ops ; sub sp, sp, frame_size);
+        }
+        self.body.iter().for_each(|inst| lower(ops, inst));
+        if frame_size > 0 {
+            aarch64!(ops ; add sp, sp, frame_size);
+        }
+        self.epilogue.iter().for_each(|inst| lower(ops, inst));
+        aarch64!(ops ; ret);
+        start
+    }
+}
+
+/// A generated function in executable memory
+pub struct JitFunction<S: Signature> {
+    buffer: ExecutableBuffer,
+    start: AssemblyOffset,
+    _signature: PhantomData<S>,
+}
+
+impl<S: Signature> JitFunction<S> {
+    /// The machine code
+    pub fn code(&self) -> &[u8] {
+        &self.buffer[self.start.0..]
+    }
+
+    /// The function as `extern "C" fn(A, B, ..) -> R`
+    ///
+    /// # Safety
+    ///
+    /// It can only be called on aarch64 and while `self` is alive
+    pub unsafe fn get(&self) -> S::Extern {
+        let ptr = self.buffer.ptr(self.start);
+        unsafe { std::mem::transmute_copy(&ptr) }
+    }
+}
+
+/// Generate a function like [`function`] for the host platform and assemble it into executable
+/// memory
+pub fn jit<S: Signature>(
+    body: impl FnOnce(&mut Allocator, S::Params) -> (Vec<Instruction>, <S::Ret as AbiType>::Regs),
+) -> JitFunction<S> {
+    let f = function::<S>("jit", Platform::host(), body);
+    let mut ops = Assembler::new().expect("failed to map memory for the assembler");
+    let start = f.assemble(&mut ops);
+    JitFunction {
+        buffer: ops
+            .finalize()
+            .unwrap_or_else(|_| panic!("failed to make the code executable")),
+        start,
+        _signature: PhantomData,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::wide_kernel;
+
+    fn words(code: &[u8]) -> Vec<u32> {
+        code.chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    fn mulu128() -> JitFunction<fn(u64, u64) -> [u64; 2]> {
+        jit::<fn(u64, u64) -> [u64; 2]>(|asm, (a, b)| {
+            let c: [Reg<u64>; 2] = std::array::from_fn(|_| asm.fresh());
+            let instructions = [mul(&c[0], &a, &b), umulh(&c[1], &a, &b)];
+            (instructions.into_iter().flatten().collect(), c)
+        })
+    }
+
+    #[test]
+    fn jit_mulu128() {
+        // mul x2, x0, x1; umulh x0, x0, x1; mov x1, x0; mov x0, x2; ret
+        assert_eq!(
+            words(mulu128().code()),
+            [0x9b017c02, 0x9bc17c00, 0xaa0003e1, 0xaa0203e0, 0xd65f03c0]
+        );
+    }
+
+    #[test]
+    fn jit_immediates_and_flags() {
+        let f = jit::<fn(u64, u64) -> [u64; 3]>(|asm, (a, b)| {
+            let c: [Reg<u64>; 3] = std::array::from_fn(|_| asm.fresh());
+            let instructions = [
+                mov(&c[2], 0x1234_0000_0000_5678),
+                adds(&c[0], &a, &b),
+                adcs(&c[1], &a, &b),
+                cinc(&c[1], &c[1], "hs"),
+            ];
+            (instructions.into_iter().flatten().collect(), c)
+        });
+        // mov x2, #0x5678; movk x2, #0x1234, lsl #48; adds x3, x0, x1; adcs x0, x0, x1;
+        // cinc x0, x0, hs; str x3, [x8]; str x0, [x8, #8]; str x2, [x8, #16]; ret
+        assert_eq!(
+            words(f.code()),
+            [
+                0xd28acf02, 0xf2e24682, 0xab010003, 0xba010000, 0x9a803400, 0xf9000103, 0xf9000500,
+                0xf9000902, 0xd65f03c0
+            ]
+        );
+    }
+
+    #[test]
+    fn jit_saves_and_spills() {
+        // Every instruction of the text form is one word
+        let body = |asm: &mut Allocator, (_a,): (Reg<u64>,)| {
+            let (instructions, sum, _) = wide_kernel(asm);
+            (instructions, sum)
+        };
+        let f = function::<fn(u64) -> u64>("wide", Platform::host(), body);
+        let lines = f
+            .to_string()
+            .lines()
+            .filter(|line| line.starts_with("    "))
+            .count();
+        assert!(f.frame_size > 0);
+        assert_eq!(jit::<fn(u64) -> u64>(body).code().len(), 4 * lines);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn call_jit_functions() {
+        let f = mulu128();
+        let mulu128 = unsafe { f.get() };
+        let product = u64::MAX as u128 * 3;
+        assert_eq!(
+            mulu128(u64::MAX, 3),
+            [product as u64, (product >> 64) as u64]
+        );
+
+        let f = jit::<fn([Simd<u64, 2>; 5], u64) -> [u64; 5]>(|asm, (_a, b)| {
+            let c: [Reg<u64>; 5] = std::array::from_fn(|_| asm.fresh());
+            let instructions = (0..5).flat_map(|i| mov(&c[i], i as u64 + 1));
+            (instructions.chain(mul(&c[4], &c[4], &b)).collect(), c)
+        });
+        let scale = unsafe { f.get() };
+        assert_eq!(scale([core::simd::Simd::splat(0); 5], 10), [1, 2, 3, 4, 50]);
+
+        let f = jit::<fn(Simd<u64, 2>, u64) -> [Simd<u64, 2>; 2]>(|asm, (s, c)| {
+            let t = asm.fresh();
+            (dup2d(&t, &c), [t, s])
+        });
+        let splat = unsafe { f.get() };
+        let s = core::simd::Simd::from_array([1, 2]);
+        assert_eq!(splat(s, 7), [core::simd::Simd::splat(7), s]);
+
+        let mut expected = 0;
+        let f = jit::<fn(u64) -> u64>(|asm, (_a,)| {
+            let (instructions, sum, sum_value) = wide_kernel(asm);
+            expected = sum_value;
+            (instructions, sum)
+        });
+        let wide = unsafe { f.get() };
+        assert_eq!(wide(0), expected);
+    }
+}
@@ -1,6 +1,15 @@
 #![feature(iter_intersperse)]
+#![feature(portable_simd)]
+mod function;
+mod inline;
+mod jit;
+
+pub use function::*;
+pub use inline::*;
+pub use jit::*;
+
 use std::{
-    collections::{BTreeSet, HashSet, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     marker::PhantomData,
     mem::{self},
 };
@@ -35,6 +44,14 @@ enum Mod {
     Imm(u64),
     Idx(u64),
     Cond(String),
+    // Byte offset from sp
+    Offset(u64),
+    // Lower sp by this many bytes before the store
+    PreIndex(u64),
+    // Raise sp by this many bytes after the load
+    PostIndex(u64),
+    // Byte offset from the address in the last source register
+    Base(u64),
 }
 
 // TODO This could benefit from having really different types for FreshRegister and
@@ -53,6 +70,7 @@ impl std::fmt::Display for Addressing {
             Addressing::V => write!(f, "v"),
             Addressing::D => write!(f, "d"),
             Addressing::X => write!(f, "x"),
+            Addressing::Q => write!(f, "q"),
         }
     }
 }
@@ -60,12 +78,32 @@ impl std::fmt::Display for Addressing {
 impl<R: std::fmt::Display + Copy> InstructionF<R> {
     // TODO this might be better as Display and/or using Formatter
     fn format_instruction(&self) -> String {
+        self.format_with(|reg| reg.to_string())
+    }
+
+    // Format with the given spelling of the registers
+    fn format_with(&self, register: impl Fn(TypedSizedRegister<R>) -> String) -> String {
         let mut phys_regs = vec![self.dest];
         phys_regs.append(&mut self.src.clone());
+        let base = match self.modifiers {
+            Mod::Base(_) => phys_regs.pop(),
+            _ => None,
+        };
 
+        // Loads and stores name the full SIMD/FP register q
+        let memory = matches!(
+            self.modifiers,
+            Mod::Offset(_) | Mod::PreIndex(_) | Mod::PostIndex(_) | Mod::Base(_)
+        );
         let regs: String = phys_regs
             .iter()
-            .map(|x| x.to_string())
+            .map(|&x| match x.addressing {
+                Addressing::V if memory => register(TypedSizedRegister {
+                    reg: x.reg,
+                    addressing: Addressing::Q,
+                }),
+                _ => register(x),
+            })
             .intersperse(", ".to_string())
             .collect();
 
@@ -74,6 +112,10 @@ impl<R: std::fmt::Display + Copy> InstructionF<R> {
             Mod::Imm(imm) => format!(", #{imm}"),
             Mod::Cond(cond) => format!(", {cond}"),
             Mod::Idx(idx) => format!("[{idx}]"),
+            Mod::Offset(offset) => format!(", [sp, #{offset}]"),
+            Mod::PreIndex(offset) => format!(", [sp, #-{offset}]!"),
+            Mod::PostIndex(offset) => format!(", [sp], #{offset}"),
+            Mod::Base(offset) => format!(", [{}, #{offset}]", register(base.unwrap())),
         };
         let inst = &self.opcode;
         format!("{inst} {regs}{extra}")
@@ -237,6 +279,8 @@ pub enum Addressing {
     // SIMD/FP
     V,
     D,
+    // Full SIMD/FP register in loads and stores
+    Q,
 }
 
 /// TODO new name under this construction
@@ -326,6 +370,8 @@ impl std::fmt::Display for HardwareRegister {
 enum RegisterState {
     Unassigned,
     Assigned(TypedSizedRegister<HardwareRegister>),
+    // Stored in a stack slot, the addressing is the one it gets when it's reloaded
+    Spilled(Addressing, u64),
     Dropped,
 }
 
@@ -385,18 +431,33 @@ pub fn input<T>(
 where
     T: RegisterSource,
 {
-    let fresh = asm.fresh();
+    let fresh: Reg<T> = asm.fresh();
+    assign_input(
+        mapping,
+        phys_registers,
+        fresh.to_typed_register(),
+        HardwareRegister(phys),
+    );
+    fresh
+}
 
-    let hw_reg = HardwareRegister(phys);
+// Assign a fresh register to a specific hardware register before the allocation
+fn assign_input(
+    mapping: &mut RegisterMapping,
+    phys_registers: &mut RegisterBank,
+    fresh: TypedSizedRegister<FreshRegister>,
+    phys: HardwareRegister,
+) {
+    let hw_reg = TypedSizedRegister {
+        reg: phys,
+        addressing: fresh.addressing,
+    };
 
-    let pool = T::get_register_pool(phys_registers);
-    if !pool.remove(&hw_reg) {
-        panic!("{:?} is already in use", phys)
+    if !phys_registers.take_register(hw_reg) {
+        panic!("{:?} is already in use or reserved by the ABI", phys.0)
     }
 
-    *mapping.index_mut(fresh.reg) = RegisterState::Assigned(T::to_typed_register(hw_reg));
-
-    fresh
+    *mapping.index_mut(*fresh.as_fresh()) = RegisterState::Assigned(hw_reg);
 }
 
 pub struct Seen(HashSet<FreshRegister>);
@@ -415,27 +476,169 @@ impl Seen {
     }
 }
 
+/// Platforms differ in what AAPCS64 leaves to them, which for the register allocation is x18
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Platform {
+    /// x18 is reserved by the OS and is never handed out
+    Apple,
+    /// x18 is an ordinary temporary register
+    Linux,
+}
+
+impl Platform {
+    pub fn host() -> Self {
+        if cfg!(target_vendor = "apple") {
+            Platform::Apple
+        } else {
+            Platform::Linux
+        }
+    }
+
+    /// The assembly name of a C symbol, Apple prefixes it with an underscore
+    pub fn symbol(self, name: &str) -> String {
+        match self {
+            Platform::Apple => format!("_{name}"),
+            Platform::Linux => name.to_string(),
+        }
+    }
+}
+
+// Registers that AAPCS64 requires a function to preserve. Only the lower 64 bits of v8-v15.
+const CALLEE_SAVED_X: std::ops::RangeInclusive<u64> = 19..=28;
+const CALLEE_SAVED_V: std::ops::RangeInclusive<u64> = 8..=15;
+
+/// The hardware registers that are free to be assigned, following AAPCS64.
+///
+/// x29 (frame pointer), x30 (link register) and sp are never handed out, x18 depends on the
+/// [`Platform`]. The callee-saved x19-x28 and v8-v15 are only handed out when enabled and after
+/// the caller-saved registers, in which case the ones that have been used have to be saved by
+/// [`RegisterBank::prologue`] and restored by [`RegisterBank::epilogue`].
 #[derive(Debug)]
 pub struct RegisterBank {
     x: RegisterPool,
     v: RegisterPool,
+    // Callee-saved registers that have been handed out
+    saved_x: BTreeSet<HardwareRegister>,
+    saved_v: BTreeSet<HardwareRegister>,
 }
 
 impl RegisterBank {
+    /// Caller-saved registers without x18, such that no saves are needed and the result is valid
+    /// on every platform whichever one generates it
     pub fn new() -> Self {
+        Self::with_abi(Platform::Apple, false)
+    }
+
+    pub fn with_abi(platform: Platform, callee_saved: bool) -> Self {
+        let x = (0..=28).filter(|r| {
+            (*r != 18 || platform == Platform::Linux)
+                && (callee_saved || !CALLEE_SAVED_X.contains(r))
+        });
+        let v = (0..=31).filter(|r| callee_saved || !CALLEE_SAVED_V.contains(r));
+        Self {
+            x: x.map(HardwareRegister).collect(),
+            v: v.map(HardwareRegister).collect(),
+            saved_x: BTreeSet::new(),
+            saved_v: BTreeSet::new(),
+        }
+    }
+
+    // Operand slots of an asm! block instead of hardware registers, as many as LLVM has registers
+    // for operands: x0-x28 without x18 and x19, and v0-v31
+    fn operands() -> Self {
         Self {
-            x: BTreeSet::from_iter((0..=30).map(HardwareRegister)),
-            v: BTreeSet::from_iter((0..=30).map(HardwareRegister)),
+            x: (0..27).map(HardwareRegister).collect(),
+            v: (0..32).map(HardwareRegister).collect(),
+            saved_x: BTreeSet::new(),
+            saved_v: BTreeSet::new(),
+        }
+    }
+
+    // Take a register from the pool, caller-saved ones first
+    fn take(&mut self, addr: Addressing) -> Option<HardwareRegister> {
+        let callee_saved = Self::callee_saved(addr);
+        let pool = self.get_register_pool(addr);
+        let reg = *pool
+            .iter()
+            .find(|r| !callee_saved.contains(&r.0))
+            .or(pool.first())?;
+        self.take_register(TypedSizedRegister {
+            reg,
+            addressing: addr,
+        });
+        Some(reg)
+    }
+
+    // Take a specific register from the pool, false if it isn't in there
+    fn take_register(&mut self, register: TypedSizedRegister<HardwareRegister>) -> bool {
+        if !self
+            .get_register_pool(register.addressing)
+            .remove(&register.reg)
+        {
+            return false;
+        }
+        if Self::callee_saved(register.addressing).contains(&register.reg.0) {
+            match register.addressing {
+                Addressing::X => self.saved_x.insert(register.reg),
+                Addressing::V | Addressing::D | Addressing::Q => self.saved_v.insert(register.reg),
+            };
         }
+        true
+    }
+
+    fn callee_saved(addr: Addressing) -> std::ops::RangeInclusive<u64> {
+        match addr {
+            Addressing::X => CALLEE_SAVED_X,
+            Addressing::V | Addressing::D | Addressing::Q => CALLEE_SAVED_V,
+        }
+    }
+
+    /// Push the callee-saved registers that have been handed out onto the stack, 16 bytes per
+    /// pair. Has to run before the instructions and before sp is lowered for spill slots.
+    pub fn prologue(&self) -> Vec<InstructionF<HardwareRegister>> {
+        self.saved_pairs()
+            .into_iter()
+            .map(|pair| stack_pair_instruction(pair, ["str", "stp"], Mod::PreIndex(16)))
+            .collect()
+    }
+
+    /// Pop the registers pushed by [`RegisterBank::prologue`] in reverse order
+    pub fn epilogue(&self) -> Vec<InstructionF<HardwareRegister>> {
+        self.saved_pairs()
+            .into_iter()
+            .rev()
+            .map(|pair| stack_pair_instruction(pair, ["ldr", "ldp"], Mod::PostIndex(16)))
+            .collect()
+    }
+
+    fn saved_pairs(&self) -> Vec<Vec<TypedSizedRegister<HardwareRegister>>> {
+        let x: Vec<_> = self
+            .saved_x
+            .iter()
+            .map(|&reg| u64::to_typed_register(reg))
+            .collect();
+        let d: Vec<_> = self
+            .saved_v
+            .iter()
+            .map(|&reg| f64::to_typed_register(reg))
+            .collect();
+        x.chunks(2)
+            .chain(d.chunks(2))
+            .map(|pair| pair.to_vec())
+            .collect()
     }
 
     fn get_register_pool(&mut self, addr: Addressing) -> &mut RegisterPool {
         match addr {
             Addressing::X => &mut self.x,
-            Addressing::V | Addressing::D => &mut self.v,
+            Addressing::V | Addressing::D | Addressing::Q => &mut self.v,
         }
     }
 
+    fn same_pool(a: Addressing, b: Addressing) -> bool {
+        (a == Addressing::X) == (b == Addressing::X)
+    }
+
     /// Return the hardware register back into the register pool
     fn insert(&mut self, register: TypedSizedRegister<HardwareRegister>) -> bool {
         self.get_register_pool(register.addressing)
@@ -454,16 +657,25 @@ pub fn interleave(
         .collect()
 }
 
+/// Size of a stack slot, every slot fits a full SIMD/FP register and keeps sp 16 byte aligned
+const SLOT_SIZE: u64 = 16;
+
 #[derive(Debug)]
-pub struct RegisterMapping(Vec<RegisterState>);
+pub struct RegisterMapping {
+    states: Vec<RegisterState>,
+    // Stack slots that have been used before and are free again
+    free_slots: BTreeSet<u64>,
+    slots: u64,
+}
 
 impl std::fmt::Display for RegisterMapping {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Register Mapping: [")?;
-        for (i, state) in self.0.iter().enumerate() {
+        for (i, state) in self.states.iter().enumerate() {
             match state {
                 RegisterState::Unassigned => write!(f, "  {}: U", i)?,
                 RegisterState::Assigned(reg) => write!(f, "  {}: M{}", i, reg)?,
+                RegisterState::Spilled(_, slot) => write!(f, "  {}: S{}", i, slot)?,
                 RegisterState::Dropped => write!(f, "  {}: D", i)?,
             }
             write!(f, ", ")?
@@ -475,25 +687,36 @@ impl std::fmt::Display for RegisterMapping {
 
 impl RegisterMapping {
     pub fn new() -> Self {
-        // TODO Needs to be equal to the number of free register in the allocator once it is finished
-        // but also needs space for the elements in the beginning
-        // In the beginning there can't be more than all the vector registers combined, so that can be allocated initially
-        // get_or_allocate_register needs to deal with the resizing
-        Self(
-            std::iter::repeat_with(|| RegisterState::Unassigned)
+        // In the beginning there can't be more than all the vector registers combined, so that can
+        // be allocated initially. index_mut grows it for kernels with more fresh registers.
+        Self {
+            states: std::iter::repeat_with(|| RegisterState::Unassigned)
                 .take(30)
                 .collect::<Vec<_>>(),
-        )
+            free_slots: BTreeSet::new(),
+            slots: 0,
+        }
     }
 
-    // Get the physical register for a source register
+    /// Bytes of stack the spill slots use at [sp, sp + frame_size). The function that contains
+    /// the instructions has to reserve them, the size keeps sp 16 byte aligned.
+    pub fn frame_size(&self) -> u64 {
+        self.slots * SLOT_SIZE
+    }
+
+    // Get the physical register for a source register, reloading it if it has been spilled
     fn get_register(
-        &self,
+        &mut self,
+        register_bank: &mut RegisterBank,
+        spill: &mut Spill,
         fresh: TypedSizedRegister<FreshRegister>,
     ) -> TypedSizedRegister<HardwareRegister> {
         match *self.index(*fresh.as_fresh()) {
             RegisterState::Unassigned => unreachable!("{fresh:?} has not been assigned yet"),
             RegisterState::Assigned(reg) => reg,
+            RegisterState::Spilled(addr, slot) => {
+                self.reload(register_bank, spill, *fresh.as_fresh(), addr, slot)
+            }
             RegisterState::Dropped => unreachable!("{fresh:?} already has been dropped"),
         }
     }
@@ -502,30 +725,87 @@ impl RegisterMapping {
     fn get_or_allocate_register(
         &mut self,
         register_bank: &mut RegisterBank,
+        spill: &mut Spill,
         typed_register: TypedSizedRegister<FreshRegister>,
     ) -> TypedSizedRegister<HardwareRegister> {
-        // Possible to do a mutable reference here
-        let entry = self.index_mut(*typed_register.as_fresh());
-        match *entry {
+        let fresh = *typed_register.as_fresh();
+        match *self.index(fresh) {
             RegisterState::Unassigned => {
-                let addr = typed_register.addressing;
-                let pool = register_bank.get_register_pool(addr);
-
-                let hw_reg = pool.pop_first().expect("ran out of registers");
-
-                let typed_hw_reg = TypedSizedRegister {
-                    reg: hw_reg,
-                    addressing: addr,
-                };
-
-                *entry = RegisterState::Assigned(typed_hw_reg);
+                let typed_hw_reg = self.allocate(register_bank, spill, typed_register.addressing);
+                *self.index_mut(fresh) = RegisterState::Assigned(typed_hw_reg);
                 typed_hw_reg
             }
             RegisterState::Assigned(reg) => reg,
+            // Instructions like fmla also read their destination, so it's always reloaded
+            RegisterState::Spilled(addr, slot) => {
+                self.reload(register_bank, spill, fresh, addr, slot)
+            }
             RegisterState::Dropped => unreachable!("{typed_register:?} already has been dropped"),
         }
     }
 
+    // Take a free hardware register, or spill the one whose next use is the furthest away
+    fn allocate(
+        &mut self,
+        register_bank: &mut RegisterBank,
+        spill: &mut Spill,
+        addr: Addressing,
+    ) -> TypedSizedRegister<HardwareRegister> {
+        let hw_reg = match register_bank.take(addr) {
+            Some(hw_reg) => hw_reg,
+            None => self.spill(spill, addr),
+        };
+        TypedSizedRegister {
+            reg: hw_reg,
+            addressing: addr,
+        }
+    }
+
+    // Store the register in the same pool as addr with the furthest next use on the stack and
+    // hand out its hardware register
+    fn spill(&mut self, spill: &mut Spill, addr: Addressing) -> HardwareRegister {
+        let (victim, reg) = self
+            .states
+            .iter()
+            .enumerate()
+            .filter_map(|(i, state)| match state {
+                RegisterState::Assigned(reg)
+                    if RegisterBank::same_pool(reg.addressing, addr)
+                        && !spill.in_use.contains(&FreshRegister(i as u64)) =>
+                {
+                    Some((FreshRegister(i as u64), *reg))
+                }
+                _ => None,
+            })
+            .max_by_key(|(fresh, _)| spill.next_use.after(*fresh, spill.position))
+            .expect("ran out of registers: none of them can be spilled");
+
+        let slot = self.free_slots.pop_first().unwrap_or_else(|| {
+            self.slots += 1;
+            self.slots - 1
+        });
+        spill.out.push(stack_instruction("str", reg, slot));
+        *self.index_mut(victim) = RegisterState::Spilled(reg.addressing, slot);
+        reg.reg
+    }
+
+    // Load a spilled register into a hardware register. The stack slot is freed as the register
+    // might be modified after this.
+    fn reload(
+        &mut self,
+        register_bank: &mut RegisterBank,
+        spill: &mut Spill,
+        fresh: FreshRegister,
+        addr: Addressing,
+        slot: u64,
+    ) -> TypedSizedRegister<HardwareRegister> {
+        let reg = self.allocate(register_bank, spill, addr);
+        spill.out.push(stack_instruction("ldr", reg, slot));
+        self.free_slots.insert(slot);
+        *self.index_mut(fresh) = RegisterState::Assigned(reg);
+        reg
+    }
+
     // Once a fresh register goes out of scope the hardware register that was assigned to that fresh register
     // can be returned to the register bank.
     fn free_register(&mut self, register_bank: &mut RegisterBank, fresh: FreshRegister) -> bool {
@@ -543,6 +823,7 @@ impl RegisterMapping {
                 );
                 new
             }
+            RegisterState::Spilled(_, slot) => self.free_slots.insert(slot),
             RegisterState::Dropped => {
                 unreachable!("A register that has been dropped can't be dropped again")
             }
@@ -556,6 +837,7 @@ impl RegisterMapping {
         match self.index(reg.reg) {
             RegisterState::Unassigned => panic!("requested output register for some"),
             RegisterState::Assigned(hw_reg) => format!("{}", hw_reg),
+            RegisterState::Spilled(_, slot) => format!("[sp, #{}]", slot * SLOT_SIZE),
             RegisterState::Dropped => "Dropped".to_string(),
         }
     }
@@ -564,13 +846,91 @@ impl RegisterMapping {
 /// We do not implement the Index Trait as that would leak the private RegisterState
 impl RegisterMapping {
     fn index(&self, idx: FreshRegister) -> &RegisterState {
-        &self.0[idx.0 as usize]
+        self.states
+            .get(idx.0 as usize)
+            .unwrap_or(&RegisterState::Unassigned)
     }
     fn index_mut(&mut self, idx: FreshRegister) -> &mut RegisterState {
-        &mut self.0[idx.0 as usize]
+        let idx = idx.0 as usize;
+        if idx >= self.states.len() {
+            self.states
+                .resize_with(idx + 1, || RegisterState::Unassigned);
+        }
+        &mut self.states[idx]
+    }
+}
+
+// A str or ldr of a register to a stack slot. Registers of the SIMD/FP pool are always stored in
+// full as a fresh register can be addressed as both V and D.
+fn stack_instruction(
+    opcode: &str,
+    reg: TypedSizedRegister<HardwareRegister>,
+    slot: u64,
+) -> InstructionF<HardwareRegister> {
+    let addressing = match reg.addressing {
+        Addressing::X => Addressing::X,
+        Addressing::V | Addressing::D | Addressing::Q => Addressing::Q,
+    };
+    InstructionF {
+        opcode: opcode.to_string(),
+        dest: TypedSizedRegister {
+            reg: reg.reg,
+            addressing,
+        },
+        src: vec![],
+        modifiers: Mod::Offset(slot * SLOT_SIZE),
+    }
+}
+
+// A single or paired store/load of saved registers, [single, pair] opcodes
+fn stack_pair_instruction(
+    pair: Vec<TypedSizedRegister<HardwareRegister>>,
+    opcodes: [&str; 2],
+    modifiers: Mod,
+) -> InstructionF<HardwareRegister> {
+    InstructionF {
+        opcode: opcodes[pair.len() - 1].to_string(),
+        dest: pair[0],
+        src: pair[1..].to_vec(),
+        modifiers,
+    }
+}
+
+/// Positions of the instructions that use each fresh register, to find the register whose next
+/// use is the furthest away when one has to be spilled
+struct NextUse(HashMap<FreshRegister, Vec<usize>>);
+
+impl NextUse {
+    fn new(instructions: &[Instruction]) -> Self {
+        let mut uses: HashMap<_, Vec<_>> = HashMap::new();
+        for (position, instruction) in instructions.iter().enumerate() {
+            for reg in instruction.extract_registers() {
+                uses.entry(*reg.as_fresh()).or_default().push(position);
+            }
+        }
+        Self(uses)
+    }
+
+    // The first use at or after position, usize::MAX for registers that are only live out
+    fn after(&self, fresh: FreshRegister, position: usize) -> usize {
+        let Some(uses) = self.0.get(&fresh) else {
+            return usize::MAX;
+        };
+        uses.get(uses.partition_point(|&p| p < position))
+            .copied()
+            .unwrap_or(usize::MAX)
     }
 }
 
+// State of hardware_register_allocation that spilling needs
+struct Spill<'a> {
+    next_use: &'a NextUse,
+    position: usize,
+    // The registers of the current instruction, which can't be spilled
+    in_use: HashSet<FreshRegister>,
+    out: Vec<InstructionF<HardwareRegister>>,
+}
+
 // TODO optimise
 // The invariant is that the hashset will only contain the sources and therefore always free to deallocate
 // because if not it means that it's either been used earlier so it would not show up in release.
@@ -602,6 +962,12 @@ pub fn liveness_analysis(
     commands
 }
 
+/// Assign hardware registers to the fresh registers of the instructions.
+///
+/// When a pool runs out the register whose next use is the furthest away is stored to a stack
+/// slot with `str` and loaded again with `ldr` before its next use. The slots are addressed from
+/// sp, see [`RegisterMapping::frame_size`]. Registers that are still spilled at the end are
+/// loaded again such that the outputs are in registers.
 pub fn hardware_register_allocation(
     mapping: &mut RegisterMapping,
     register_bank: &mut RegisterBank,
@@ -615,33 +981,54 @@ pub fn hardware_register_allocation(
         "The instructions and release collections need to be the same lenght"
     );
 
-    let f = |(instruction, release): (Instruction, HashSet<_>)| {
-        // println!();
-        // println!("mapping: {mapping}");
-        // println!("bank: {register_bank:?}");
-        // println!("instruction: {instruction:?}");
-        // println!("release: {release:?}");
-        // std::io::stdout().flush().unwrap();
+    let next_use = NextUse::new(&instructions);
+    let mut spill = Spill {
+        next_use: &next_use,
+        position: 0,
+        in_use: HashSet::new(),
+        out: Vec::with_capacity(instructions.len()),
+    };
+
+    for (position, (instruction, release)) in instructions.into_iter().zip(releases).enumerate() {
+        spill.position = position;
+        spill.in_use = instruction
+            .extract_registers()
+            .into_iter()
+            .map(|tr| *tr.as_fresh())
+            .collect();
 
         let src = instruction
             .src
             .into_iter()
-            .map(|s| mapping.get_register(s))
+            .map(|s| mapping.get_register(register_bank, &mut spill, s))
             .collect();
         // assert on the return of free register?
         release.into_iter().for_each(|fresh| {
             mapping.free_register(register_bank, fresh);
         });
-        let dest = mapping.get_or_allocate_register(register_bank, instruction.dest);
-        InstructionF {
+        let dest = mapping.get_or_allocate_register(register_bank, &mut spill, instruction.dest);
+        spill.out.push(InstructionF {
             opcode: instruction.opcode,
             dest,
             src,
             modifiers: instruction.modifiers,
+        });
+    }
+
+    // Everything that is still live has to stay in a register now
+    let live_out: Vec<_> = (0..mapping.states.len() as u64)
+        .map(FreshRegister)
+        .filter(|&fresh| !matches!(mapping.index(fresh), RegisterState::Dropped))
+        .collect();
+    spill.position = usize::MAX;
+    spill.in_use = live_out.iter().copied().collect();
+    for fresh in live_out {
+        if let RegisterState::Spilled(addr, slot) = *mapping.index(fresh) {
+            mapping.reload(register_bank, &mut spill, fresh, addr, slot);
         }
-    };
+    }
 
-    instructions.into_iter().zip(releases).map(f).collect()
+    spill.out
 }
 
 pub fn print_instructions<R: std::fmt::Display + Copy>(instrs: &[InstructionF<R>]) {
@@ -649,3 +1036,193 @@ pub fn print_instructions<R: std::fmt::Display + Copy>(instrs: &[InstructionF<R>
         .iter()
         .for_each(|inst| println!("{}", inst.format_instruction()));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // Execute the scalar instructions, including the spills and reloads, on a model of the
+    // registers and the stack. Memory is addressed by the value of a base register.
+    pub(crate) fn execute(
+        instructions: &[InstructionF<HardwareRegister>],
+        mut regs: BTreeMap<HardwareRegister, u64>,
+        memory: &mut HashMap<u64, u64>,
+    ) -> BTreeMap<HardwareRegister, u64> {
+        let mut stack = HashMap::new();
+        for instruction in instructions {
+            let src: Vec<u64> = instruction.src.iter().map(|s| regs[&s.reg]).collect();
+            let value = match (instruction.opcode.as_str(), &instruction.modifiers) {
+                ("mov", Mod::Imm(imm)) => *imm,
+                ("mov", Mod::None) => src[0],
+                ("mul", Mod::None) => src[0].wrapping_mul(src[1]),
+                ("umulh", Mod::None) => ((src[0] as u128 * src[1] as u128) >> 64) as u64,
+                ("adds", Mod::None) => src[0].wrapping_add(src[1]),
+                ("str", Mod::Offset(offset)) => {
+                    stack.insert(*offset, regs[&instruction.dest.reg]);
+                    continue;
+                }
+                ("ldr", Mod::Offset(offset)) => stack[offset],
+                ("str", Mod::Base(offset)) => {
+                    memory.insert(src[0] + offset, regs[&instruction.dest.reg]);
+                    continue;
+                }
+                ("ldr", Mod::Base(offset)) => memory[&(src[0] + offset)],
+                _ => unreachable!("{instruction:?}"),
+            };
+            regs.insert(instruction.dest.reg, value);
+        }
+        regs
+    }
+
+    // Sum of the products of 48 values that are all live at the same time
+    pub(crate) fn wide_kernel(asm: &mut Allocator) -> (Vec<Instruction>, Reg<u64>, u64) {
+        const N: u64 = 48;
+        let values: Vec<Reg<u64>> = (0..N).map(|_| asm.fresh()).collect();
+        let products: Vec<Reg<u64>> = (0..N).map(|_| asm.fresh()).collect();
+        let sum = asm.fresh();
+
+        let mut instructions: Vec<AtomicInstruction> = vec![mov(&sum, 0)];
+        for (i, value) in values.iter().enumerate() {
+            instructions.push(mov(value, i as u64 + 1));
+        }
+        for i in 0..values.len() {
+            let j = values.len() - 1 - i;
+            instructions.push(mul(&products[i], &values[i], &values[j]));
+        }
+        for product in &products {
+            instructions.push(adds(&sum, &sum, product));
+        }
+
+        let expected = (1..=N).map(|i| i * (N + 1 - i)).sum();
+        (instructions.into_iter().flatten().collect(), sum, expected)
+    }
+
+    // Allocate and run the wide kernel, returns the number of spills
+    fn check_wide_kernel(mut register_bank: RegisterBank) -> usize {
+        let mut asm = Allocator::new();
+        let mut mapping = RegisterMapping::new();
+        let (instructions, sum, expected) = wide_kernel(&mut asm);
+        let len = instructions.len();
+
+        let mut seen = Seen::new();
+        seen.output_interface(&sum);
+        let releases = liveness_analysis(&mut seen, &instructions);
+        let out =
+            hardware_register_allocation(&mut mapping, &mut register_bank, instructions, releases);
+
+        let stores = out.iter().filter(|i| i.opcode == "str").count();
+        let loads = out.iter().filter(|i| i.opcode == "ldr").count();
+        assert!(stores > 0);
+        assert_eq!(stores, loads);
+        assert_eq!(out.len(), len + stores + loads);
+        assert!(mapping.frame_size() > 0);
+        assert_eq!(mapping.frame_size() % 16, 0);
+
+        let RegisterState::Assigned(hw_sum) = *mapping.index(sum.reg) else {
+            panic!("the output has to end up in a register");
+        };
+        let regs = execute(&out, BTreeMap::new(), &mut HashMap::new());
+        assert_eq!(regs[&hw_sum.reg], expected);
+        stores
+    }
+
+    #[test]
+    fn spill_live_registers() {
+        check_wide_kernel(RegisterBank::new());
+    }
+
+    #[test]
+    fn callee_saved_registers_reduce_spills() {
+        let caller_saved = check_wide_kernel(RegisterBank::with_abi(Platform::Apple, false));
+        let callee_saved = check_wide_kernel(RegisterBank::with_abi(Platform::Apple, true));
+        assert!(callee_saved < caller_saved);
+    }
+
+    #[test]
+    fn reserved_registers() {
+        let apple = RegisterBank::with_abi(Platform::Apple, false);
+        let x: Vec<_> = apple.x.iter().map(|r| r.0).collect();
+        assert_eq!(x, (0..=17).collect::<Vec<_>>());
+        assert_eq!(apple.v.len(), 24);
+        assert_eq!(RegisterBank::new().x, apple.x);
+
+        let linux = RegisterBank::with_abi(Platform::Linux, true);
+        assert!(linux.x.contains(&HardwareRegister(18)));
+        for reserved in [29, 30, 31] {
+            assert!(!linux.x.contains(&HardwareRegister(reserved)));
+        }
+        assert_eq!(linux.x.len(), 29);
+        assert_eq!(linux.v.len(), 32);
+    }
+
+    #[test]
+    fn save_callee_saved_registers() {
+        let mut bank = RegisterBank::with_abi(Platform::Apple, true);
+        let x: Vec<_> = (0..20)
+            .map(|_| bank.take(Addressing::X).unwrap().0)
+            .collect();
+        assert_eq!(x[17..], [17, 19, 20]);
+        // v16-v31 come before v8
+        let v: Vec<_> = (0..25)
+            .map(|_| bank.take(Addressing::V).unwrap().0)
+            .collect();
+        assert_eq!(v[23..], [31, 8]);
+
+        let format = |instructions: Vec<InstructionF<HardwareRegister>>| {
+            instructions
+                .iter()
+                .map(|i| i.format_instruction())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            format(bank.prologue()),
+            ["stp x19, x20, [sp, #-16]!", "str d8, [sp, #-16]!"]
+        );
+        assert_eq!(
+            format(bank.epilogue()),
+            ["ldr d8, [sp], #16", "ldp x19, x20, [sp], #16"]
+        );
+    }
+
+    #[test]
+    fn no_saves_without_callee_saved_registers() {
+        let mut bank = RegisterBank::with_abi(Platform::Linux, false);
+        while bank.take(Addressing::X).is_some() {}
+        assert!(bank.prologue().is_empty());
+        assert!(bank.epilogue().is_empty());
+    }
+
+    #[test]
+    fn no_spills_when_registers_suffice() {
+        let mut asm = Allocator::new();
+        let mut mapping = RegisterMapping::new();
+        let mut register_bank = RegisterBank::new();
+        let a = asm.fresh();
+        let b = asm.fresh();
+        let c = asm.fresh();
+        let instructions: Vec<_> = [mov(&a, 3), mov(&b, 5), mul(&c, &a, &b)]
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut seen = Seen::new();
+        seen.output_interface(&c);
+        let releases = liveness_analysis(&mut seen, &instructions);
+        let out =
+            hardware_register_allocation(&mut mapping, &mut register_bank, instructions, releases);
+
+        assert_eq!(out.len(), 3);
+        assert_eq!(mapping.frame_size(), 0);
+    }
+
+    #[test]
+    fn spill_simd_registers_in_full() {
+        let reg = TypedSizedRegister {
+            reg: HardwareRegister(3),
+            addressing: Addressing::D,
+        };
+        let store = stack_instruction("str", reg, 2);
+        assert_eq!(store.format_instruction(), "str q3, [sp, #32]");
+    }
+}
@@ -103,12 +103,12 @@ fn gen_mulu128(c: &[Reg<u64>; 2], a: &Reg<u64>, b: &Reg<u64>) -> Vec<Instruction
         .collect()
 }
 
+// asm/mulu128.s is the output of build_mulu128
 #[inline(never)]
 fn call_mulu128(a: u64, b: u64) -> u128 {
-    let mut lo: u64;
-    let mut hi: u64;
-    // For now hard code since it only generated every now and then
-    unsafe { asm!("bl _mulu128", in("x0") a, in("x1") b, out("x2") lo, out("x3") hi) };
+    let lo: u64;
+    let hi: u64;
+    unsafe { asm!("bl _mulu128", inout("x0") a => lo, inout("x1") b => hi, clobber_abi("C")) };
     (hi as u128) << 64 | lo as u128
 }
 
@@ -128,26 +128,25 @@ fn inline_call_mulu128(a: u64, b: u64) -> u128 {
 }
 
 fn build_mulu128() {
-    let mut asm = Allocator::new();
-    let mut mapping = RegisterMapping::new();
-    let mut register_bank = RegisterBank::new();
-    let a = input(&mut asm, &mut mapping, &mut register_bank, 0);
-    let b = input(&mut asm, &mut mapping, &mut register_bank, 1);
-    let ret = array::from_fn(|_| asm.fresh());
-
-    let inst = gen_mulu128(&ret, &a, &b);
-
-    let mut seen_registers = Seen::new();
-    ret.iter().for_each(|r| {
-        seen_registers.output_interface(r);
+    let f = function::<fn(u64, u64) -> [u64; 2]>("mulu128", Platform::Apple, |asm, (a, b)| {
+        let ret = array::from_fn(|_| asm.fresh());
+        (gen_mulu128(&ret, &a, &b), ret)
     });
-    let releases = liveness_analysis(&mut seen_registers, &inst);
-    let physical_inst =
-        hardware_register_allocation(&mut mapping, &mut register_bank, inst, releases);
-    print_instructions(&physical_inst);
-    ret.iter()
-        .for_each(|r| println!("{}", mapping.output_register(r)));
+    print!("{f}");
 }
+
+// Rust source with smult in an asm! block, the counterpart of smult_inline in
+// experiments-lowlevel
+fn build_inline_smult() {
+    let f =
+        inline_function::<fn([u64; 4], u64) -> [u64; 5]>("smult", &["a", "b"], |asm, (a, b)| {
+            let s = array::from_fn(|_| asm.fresh());
+            let inst = smult(asm, &s, a, b).into_iter().flatten().collect();
+            (inst, s)
+        });
+    print!("{f}");
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct U128S {
@@ -180,6 +179,7 @@ fn main() {
     let r = inline_call_mulu128(5, 6);
     println!("r: {r:?}");
     build_mulu128();
+    build_inline_smult();
     interleave_test();
     simd_test();
 }